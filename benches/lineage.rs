@@ -0,0 +1,140 @@
+//! Compare the indexed lineage builder with the former row-by-row scan.
+//!
+//! Run with `cargo bench --bench lineage`
+
+use git_push_automatic::lineage::{CyclePolicy, Lineage};
+use polars::prelude::*;
+use std::time::{Duration, Instant};
+
+/// Versions sorted from the most recent to the oldest, as `prepare-tables` does. Each version is
+/// based on one of the 20 previous ones, with a new root every 50 versions.
+fn synthetic_versions(height: usize) -> DataFrame {
+    let mut seed: u64 = 42;
+    let mut references: Vec<String> = Vec::with_capacity(height);
+    let mut based_on: Vec<Option<String>> = Vec::with_capacity(height);
+    for version in 0..height {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        references.push(format!("B{:06}R-A", version));
+        based_on.push(if version % 50 == 0 {
+            None
+        } else {
+            let back = 1 + (seed >> 33) as usize % version.min(20);
+            Some(format!("B{:06}R-A", version - back))
+        });
+    }
+    references.reverse();
+    based_on.reverse();
+    df!("Reference" => references, "Based On" => based_on).unwrap()
+}
+
+// The dataframe is sorted by date in descending order (most recent day -> the oldest day)
+// Begin linking only if no other versions based on this version have been created subsequently
+fn can_start_linked_list(df: &DataFrame, current_index: usize) -> bool {
+    let reference = df
+        .column("Reference")
+        .unwrap()
+        .str()
+        .unwrap()
+        .get(current_index);
+    let based_on_col = df.column("Based On").unwrap().str().unwrap();
+    for i in 0..current_index {
+        if based_on_col.get(i) == reference {
+            return false;
+        }
+    }
+    true
+}
+
+fn linked_lists(df: &DataFrame) -> Vec<DataFrame> {
+    Lineage::new(df, CyclePolicy::Abort)
+        .unwrap()
+        .linked_lists()
+        .unwrap()
+}
+
+fn create_linked_lists_naive(df: &DataFrame) -> Vec<DataFrame> {
+    let mut linked_lists: Vec<DataFrame> = Vec::new();
+    for i in 0..df.height() {
+        if can_start_linked_list(df, i) {
+            let mut current_index = i;
+            let mut current_list = DataFrame::default();
+            // Go from top to bottom of the table, linking rows that satisfy this condition:
+            // Reference value of next row = Based On value of current row
+            while current_index < df.height() {
+                let current_row = df.slice(current_index as i64, 1);
+                current_list = current_list
+                    .vstack(&current_row)
+                    .expect("can't stack current_row into current_list");
+                let based_on = current_row
+                    .column("Based On")
+                    .expect("column Based On not found")
+                    .str()
+                    .expect("Based On column is not of type String")
+                    .get(0);
+                let next_index = (0..df.height())
+                    .filter(|&idx| idx != current_index)
+                    .find(|&j| based_on == df.column("Reference").unwrap().str().unwrap().get(j));
+
+                if let Some(next_index) = next_index {
+                    current_index = next_index
+                } else {
+                    // If no next row satisfies the condition, push the current_list to linked_lists
+                    // Only push to linked_lists if there is at least 2 element in the current link
+                    // list
+                    if current_list.height() >= 2 {
+                        // Reverse the link list (date from old -> recent) to make the root version
+                        // go to the first row
+                        linked_lists.push(current_list.reverse().clone());
+                    }
+                    break;
+                }
+            }
+        }
+    }
+    linked_lists
+}
+
+fn time<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+fn main() {
+    for height in [1_000, 4_000] {
+        let df = synthetic_versions(height);
+        let (naive, naive_time) = time(|| create_linked_lists_naive(&df));
+        let (indexed, indexed_time) = time(|| linked_lists(&df));
+        assert_eq!(
+            naive.len(),
+            indexed.len(),
+            "both builders return the same chains"
+        );
+        for (naive, indexed) in naive.iter().zip(&indexed) {
+            assert!(
+                naive.equals_missing(indexed),
+                "both builders return the same chains"
+            );
+        }
+        println!(
+            "{:>6} rows, {:>5} chains: row scan {:>10.2?}, indexed {:>10.2?} (x{:.0})",
+            height,
+            indexed.len(),
+            naive_time,
+            indexed_time,
+            naive_time.as_secs_f64() / indexed_time.as_secs_f64()
+        );
+    }
+
+    let height = 50_000;
+    let df = synthetic_versions(height);
+    let (indexed, indexed_time) = time(|| linked_lists(&df));
+    println!(
+        "{:>6} rows, {:>5} chains: indexed {:>10.2?}",
+        height,
+        indexed.len(),
+        indexed_time
+    );
+}
@@ -0,0 +1,124 @@
+use clap::{Args, Parser, Subcommand};
+use git_push_automatic::config::{PrepareConfig, WorkbookConfig};
+use git_push_automatic::duplicates::DuplicatePolicy;
+use git_push_automatic::export::ExportFormat;
+use git_push_automatic::ingest::{DateSystem, LogicielType};
+use git_push_automatic::lineage::CyclePolicy;
+use std::path::PathBuf;
+
+/// Turn the legacy Excel exports into parquet tables ready for `push-to-git`
+#[derive(Parser)]
+#[command(name = "prepare-tables", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Convert the Excel exports and write the lineage table as a .parquet file
+    Prepare(PrepareArgs),
+    /// Draw the lineage table written by `prepare` as a Graphviz or Mermaid graph
+    Export(ExportArgs),
+}
+
+/// Every flag overrides the matching key of the `[prepare]` section of `--config`
+#[derive(Args)]
+pub struct PrepareArgs {
+    /// Migration file describing the workbooks, the filters and the folders
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Workbook to read (.xls, .xlsx, .xlsm, .xlsb or .ods), as `<code|etude>=<path>`,
+    /// or `<code|etude>:<1900|1904>=<path>` to set its date system, repeat for each workbook
+    /// [default: the three exports of December 2023]
+    #[arg(long = "workbook", value_parser = parse_workbook)]
+    pub workbooks: Vec<(LogicielType, Option<DateSystem>, PathBuf)>,
+
+    /// Only keep the rows whose `Target` column contains this value [default: SD CT]
+    #[arg(long)]
+    pub target: Option<String>,
+
+    /// Also dump each workbook as a CSV file in this folder, for debugging
+    #[arg(long)]
+    pub csv_dir: Option<PathBuf>,
+
+    /// Folder where the .parquet files are written [default: ./parquets]
+    #[arg(long)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Also write each path from a root to a most recent version as `<root>_<n>.parquet`
+    #[arg(long)]
+    pub linked_lists: bool,
+
+    /// What to do when versions are based on each other in a loop [default: abort]
+    #[arg(long, value_enum)]
+    pub on_cycle: Option<CyclePolicy>,
+
+    /// Which row to keep when several rows share a `Reference` [default: latest]
+    #[arg(long, value_enum)]
+    pub on_duplicate: Option<DuplicatePolicy>,
+
+    /// File name of the workbook whose rows win with `--on-duplicate prefer-source`
+    #[arg(long)]
+    pub preferred_source: Option<String>,
+}
+
+impl PrepareArgs {
+    /// Flags given on the command line, they win over the migration file
+    pub fn overrides(&self) -> PrepareConfig {
+        PrepareConfig {
+            workbooks: self
+                .workbooks
+                .iter()
+                .map(|(logiciel_type, date_system, path)| WorkbookConfig {
+                    path: path.clone(),
+                    logiciel_type: *logiciel_type,
+                    drop_columns: None,
+                    date_system: *date_system,
+                })
+                .collect(),
+            target_filter: self.target.clone(),
+            csv_dir: self.csv_dir.clone(),
+            output_dir: self.output_dir.clone(),
+            linked_lists: self.linked_lists.then_some(true),
+            on_cycle: self.on_cycle,
+            on_duplicate: self.on_duplicate,
+            preferred_source: self.preferred_source.clone(),
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Migration file, its `[prepare]` output_dir is the folder of the lineage table
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Folder holding lineage.parquet [default: ./parquets]
+    #[arg(long)]
+    pub parquets_dir: Option<PathBuf>,
+
+    /// Only draw the lineage rooted at this reference, repeat for several lineages
+    /// [default: every lineage]
+    #[arg(long = "base-reference")]
+    pub base_references: Vec<String>,
+
+    #[arg(long, value_enum, default_value = "dot")]
+    pub format: ExportFormat,
+
+    /// File to write the graph to [default: standard output]
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+fn parse_workbook(value: &str) -> Result<(LogicielType, Option<DateSystem>, PathBuf), String> {
+    let (kind, path) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected <code|etude>=<path>, got `{}`", value))?;
+    let (logiciel_type, date_system) = match kind.split_once(':') {
+        Some((logiciel_type, date_system)) => (logiciel_type, Some(date_system.parse()?)),
+        None => (kind, None),
+    };
+    Ok((logiciel_type.parse()?, date_system, PathBuf::from(path)))
+}
@@ -0,0 +1,188 @@
+use clap::{Args, Parser, Subcommand};
+use git_push_automatic::config::{MessageConfig, PushConfig};
+use git_push_automatic::plan::{DateSource, NoZipPolicy, PlanFormat};
+use git_push_automatic::push::Backend;
+use std::path::PathBuf;
+
+/// Rebuild a git history from the tables written by `prepare-tables`
+#[derive(Parser)]
+#[command(name = "push-to-git", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Delete the repository, then recreate it with one commit per version, or append the new
+    /// versions with --incremental
+    Push(PushArgs),
+    /// List the commits, branches and tags a push would create, without touching the repository
+    Plan(PlanArgs),
+    /// Check that every version of the lineages is tagged in the repository
+    Verify(VerifyArgs),
+}
+
+/// Every flag overrides the matching key of the `[push]` section of `--config`
+#[derive(Args)]
+pub struct TableArgs {
+    /// Migration file describing the repository, the base references and the zip folders
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Repository to (re)build [default: ../legacy-to-git]
+    #[arg(long)]
+    pub repo: Option<PathBuf>,
+
+    /// Folder containing the .parquet files written by `prepare-tables` [default: ./parquets]
+    #[arg(long)]
+    pub parquets_dir: Option<PathBuf>,
+
+    /// Root of the lineage to import (repeat for several) [default: B13264R-A]
+    #[arg(long = "base-reference")]
+    pub base_references: Vec<String>,
+}
+
+impl TableArgs {
+    /// Flags given on the command line, they win over the migration file
+    pub fn overrides(&self) -> PushConfig {
+        PushConfig {
+            repo_path: self.repo.clone(),
+            parquets_dir: self.parquets_dir.clone(),
+            base_references: self.base_references.clone(),
+            ..PushConfig::default()
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct PushArgs {
+    #[command(flatten)]
+    pub tables: TableArgs,
+
+    /// Folder searched for the source zip of each version (repeat for several) [default: ./zips]
+    #[arg(long = "zips-dir")]
+    pub zips_dirs: Vec<PathBuf>,
+
+    /// Zip used when a version has no zip of its own
+    /// [default: `Sources <base-reference>.zip` in the first zips folder]
+    #[arg(long)]
+    pub default_zip: Option<PathBuf>,
+
+    /// Scratch folder the zips are extracted into [default: ./temp]
+    #[arg(long)]
+    pub temp_dir: Option<PathBuf>,
+
+    /// Domain of the e-mail addresses built from the names of the authors missing from
+    /// --authors [default: allianz.com]
+    #[arg(long)]
+    pub author_email_domain: Option<String>,
+
+    /// Mailmap-style file, `Canonical Name <e-mail> [name or initials in the exports]` per line
+    #[arg(long)]
+    pub authors: Option<PathBuf>,
+
+    /// IANA time zone of the dates of the exports, such as Europe/Paris: the commits get its
+    /// offset at their date, summer time included [default: UTC]
+    #[arg(long)]
+    pub time_zone: Option<String>,
+
+    /// Source of the commit dates, repeat to list them by priority: the first one knowing the
+    /// date of a version wins [default: creation-date archive-date expedition-date zip-mtime
+    /// parent]
+    #[arg(long = "commit-date", value_enum)]
+    pub commit_date: Vec<DateSource>,
+
+    /// Subject of the commit messages, `{Column}` or `{Column|fallback}` insert a cell of the
+    /// version [default: "[{reference}] {Comments|no_comment_found}"]
+    #[arg(long)]
+    pub subject_template: Option<String>,
+
+    /// Body of the commit messages, with the same placeholders as --subject-template
+    #[arg(long)]
+    pub body_template: Option<String>,
+
+    /// Trailer of the commit messages such as "Study-Number: {Study Number}" (repeat for several)
+    #[arg(long = "trailer")]
+    pub trailers: Vec<String>,
+
+    /// Keep the repository and append the versions that have no tag yet, the commits already
+    /// imported are left untouched
+    #[arg(long)]
+    pub incremental: bool,
+
+    /// How the sources are written: `worktree` unpacks each zip over the working tree, `odb`
+    /// streams it into git objects without a checkout, `fast-import` writes a `git fast-import`
+    /// stream [default: worktree]
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+
+    /// Create a bare repository, needs --backend odb or fast-import
+    #[arg(long)]
+    pub bare: bool,
+
+    /// Write the stream of --backend fast-import to this file, the repository is left untouched
+    /// [default: piped into `git fast-import` in the repository]
+    #[arg(long)]
+    pub fast_import_output: Option<PathBuf>,
+
+    /// What becomes of a version without a zip of its own, or with the same files as its parent:
+    /// `empty-commit` keeps the tree of its parent, `tag-parent` tags the commit of its parent,
+    /// `skip` leaves it out. The commit or tag gets a `Source-Archive: none` or
+    /// `Source-Archive: unchanged` trailer [default: empty-commit]
+    #[arg(long, value_enum)]
+    pub no_zip: Option<NoZipPolicy>,
+
+    /// Regex over the names of the zips, tar archives and folders of sources, its `reference`
+    /// group gives the version [default: "(?i)^(?:sources )?(?P<reference>.+?)(?:\.zip|\.tar|
+    /// \.tar\.gz|\.tgz|\.tar\.xz|\.txz|\.tar\.bz2|\.tbz2)?$"]
+    #[arg(long)]
+    pub zip_name_pattern: Option<String>,
+}
+
+impl PushArgs {
+    /// Flags given on the command line, they win over the migration file
+    pub fn overrides(&self) -> PushConfig {
+        PushConfig {
+            zip_folders: self.zips_dirs.clone(),
+            default_zip: self.default_zip.clone(),
+            temp_dir: self.temp_dir.clone(),
+            author_email_domain: self.author_email_domain.clone(),
+            authors: self.authors.clone(),
+            time_zone: self.time_zone.clone(),
+            commit_date: self.commit_date.clone(),
+            message: MessageConfig {
+                subject: self.subject_template.clone(),
+                body: self.body_template.clone(),
+                trailers: self.trailers.clone(),
+                ..MessageConfig::default()
+            },
+            incremental: self.incremental.then_some(true),
+            backend: self.backend,
+            bare: self.bare.then_some(true),
+            fast_import_output: self.fast_import_output.clone(),
+            no_zip: self.no_zip,
+            zip_name_pattern: self.zip_name_pattern.clone(),
+            ..self.tables.overrides()
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct PlanArgs {
+    #[command(flatten)]
+    pub push: PushArgs,
+
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: PlanFormat,
+
+    /// File to write the plan to [default: standard output]
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    #[command(flatten)]
+    pub tables: TableArgs,
+}
@@ -0,0 +1,659 @@
+//! Find the sources of each version, a zip, a tar archive or a folder, unpack them over the
+//! working tree or read their files in place.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Mode of a file of the project in a git tree
+pub const REGULAR_FILE: i32 = 0o100644;
+pub const EXECUTABLE_FILE: i32 = 0o100755;
+pub const SYMLINK: i32 = 0o120000;
+
+/// Unix file type bits of the external attributes of a zip entry
+const UNIX_FILE_TYPE: u32 = 0o170000;
+const UNIX_SYMLINK: u32 = 0o120000;
+
+/// Default `zip_name_pattern`: `Sources <reference>` or `<reference>`, followed by the extension
+/// of an archive for a file
+pub const DEFAULT_ZIP_NAME_PATTERN: &str = r"(?i)^(?:sources )?(?P<reference>.+?)(?:\.zip|\.tar|\.tar\.gz|\.tgz|\.tar\.xz|\.txz|\.tar\.bz2|\.tbz2)?$";
+
+/// Compression of a tar archive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Bzip2,
+}
+
+/// What the sources of a version are stored in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceFormat {
+    Zip,
+    Tar(Compression),
+    /// A folder holding the sources as they are
+    Directory,
+}
+
+/// File name extensions of the archives, lowercase
+const ARCHIVE_EXTENSIONS: [(&str, SourceFormat); 8] = [
+    (".zip", SourceFormat::Zip),
+    (".tar", SourceFormat::Tar(Compression::None)),
+    (".tar.gz", SourceFormat::Tar(Compression::Gzip)),
+    (".tgz", SourceFormat::Tar(Compression::Gzip)),
+    (".tar.xz", SourceFormat::Tar(Compression::Xz)),
+    (".txz", SourceFormat::Tar(Compression::Xz)),
+    (".tar.bz2", SourceFormat::Tar(Compression::Bzip2)),
+    (".tbz2", SourceFormat::Tar(Compression::Bzip2)),
+];
+
+impl SourceFormat {
+    /// Format of `path`: a folder, or an archive by the extension of its name. `None` for any
+    /// other file.
+    pub fn of(path: &Path) -> Option<Self> {
+        if path.is_dir() {
+            return Some(SourceFormat::Directory);
+        }
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        ARCHIVE_EXTENSIONS
+            .iter()
+            .find(|(extension, _)| name.ends_with(extension))
+            .map(|(_, format)| *format)
+    }
+
+    fn of_source(source: &Path) -> io::Result<Self> {
+        SourceFormat::of(source).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is neither a zip, a tar archive nor a folder",
+                    source.display()
+                ),
+            )
+        })
+    }
+}
+
+/// The sources of the zip folders by the reference their name gives, built once per run
+pub struct SourceIndex {
+    sources: HashMap<String, PathBuf>,
+    /// Archives whose file name doesn't match the pattern, left out
+    pub unmatched: Vec<PathBuf>,
+}
+
+impl SourceIndex {
+    /// Index every archive of `zip_folders` by the `reference` group of `pattern` on its file
+    /// name. A folder whose name gives a reference for which `is_reference` holds is the sources
+    /// of that version, unless it holds archives or folders named after other versions: the
+    /// other folders are searched. A reference found in several zip folders
+    /// comes from the first one; found twice in the same zip folder, sub-folders included, it is
+    /// ambiguous and fails the index.
+    pub fn build(
+        zip_folders: &[PathBuf],
+        pattern: &Regex,
+        is_reference: impl Fn(&str) -> bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut sources: HashMap<String, PathBuf> = HashMap::new();
+        let mut unmatched: Vec<PathBuf> = Vec::new();
+        let mut ambiguous: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+        for sources_folder in zip_folders {
+            let mut found: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+            // Sorted, the order of the file system doesn't matter
+            let mut entries = WalkDir::new(sources_folder)
+                .min_depth(1)
+                .sort_by_file_name()
+                .into_iter();
+            while let Some(entry) = entries.next() {
+                let Ok(entry) = entry else { continue };
+                let reference = reference_of(pattern, entry.path());
+                if entry.file_type().is_dir() {
+                    let Some(reference) = reference.filter(|reference| is_reference(reference))
+                    else {
+                        continue;
+                    };
+                    // A folder filing the sources of other versions is searched instead
+                    let files_other_versions = WalkDir::new(entry.path())
+                        .min_depth(1)
+                        .into_iter()
+                        .filter_map(|e| e.ok())
+                        .filter(|inner| {
+                            inner.file_type().is_dir() || SourceFormat::of(inner.path()).is_some()
+                        })
+                        .filter_map(|inner| reference_of(pattern, inner.path()))
+                        .any(|inner| inner != reference && is_reference(&inner));
+                    if !files_other_versions {
+                        found.entry(reference).or_default().push(entry.into_path());
+                        entries.skip_current_dir();
+                    }
+                } else if entry.file_type().is_file() && SourceFormat::of(entry.path()).is_some() {
+                    match reference {
+                        Some(reference) => {
+                            found.entry(reference).or_default().push(entry.into_path())
+                        }
+                        None => unmatched.push(entry.into_path()),
+                    }
+                }
+            }
+            for (reference, mut paths) in found {
+                if sources.contains_key(&reference) || ambiguous.contains_key(&reference) {
+                    continue;
+                }
+                match paths.len() {
+                    1 => {
+                        sources.insert(reference, paths.remove(0));
+                    }
+                    _ => {
+                        ambiguous.insert(reference, paths);
+                    }
+                }
+            }
+        }
+        if !ambiguous.is_empty() {
+            let report: Vec<String> = ambiguous
+                .iter()
+                .map(|(reference, paths)| {
+                    let paths: Vec<String> = paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect();
+                    format!("{}: {}", reference, paths.join(", "))
+                })
+                .collect();
+            return Err(format!(
+                "{} reference(s) match several sources of the same folder:\n{}",
+                ambiguous.len(),
+                report.join("\n")
+            )
+            .into());
+        }
+        Ok(SourceIndex { sources, unmatched })
+    }
+
+    /// Sources of exactly `reference`
+    pub fn find(&self, reference: &str) -> Option<&Path> {
+        self.sources.get(reference).map(PathBuf::as_path)
+    }
+}
+
+/// Digest of the path, mode and content of every file of the project in the sources: two
+/// sources with the same digest give the same tree
+pub fn files_digest(source: &Path) -> Result<git2::Oid, Box<dyn Error>> {
+    let mut files: BTreeMap<Vec<String>, (i32, git2::Oid)> = BTreeMap::new();
+    for_each_project_file(source, |path, mode, content| {
+        let mut bytes = Vec::new();
+        content.read_to_end(&mut bytes)?;
+        let blob = git2::Oid::hash_object(git2::ObjectType::Blob, &bytes)?;
+        files.insert(path.to_vec(), (mode, blob));
+        Ok(())
+    })?;
+    let mut listing = Vec::new();
+    for (path, (mode, blob)) in &files {
+        listing.extend_from_slice(format!("{:o} {} {}\n", mode, blob, path.join("/")).as_bytes());
+    }
+    Ok(git2::Oid::hash_object(git2::ObjectType::Blob, &listing)?)
+}
+
+/// Reference given by the `reference` group of `pattern` on the name of `path`
+fn reference_of(pattern: &Regex, path: &Path) -> Option<String> {
+    pattern
+        .captures(&path.file_name()?.to_string_lossy())
+        .and_then(|captures| captures.name("reference"))
+        .map(|reference| reference.as_str().trim().to_owned())
+}
+
+/// Modification time of a file of the sources
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileTime {
+    /// Wall-clock time, without a time zone, as stored by a zip
+    WallClock(NaiveDateTime),
+    /// Instant, as stored by a tar archive or a file system
+    Instant(DateTime<Utc>),
+}
+
+/// Newest modification time of the files of the sources
+pub fn newest_file_time(source: &Path) -> Result<Option<FileTime>, Box<dyn Error>> {
+    let newest = match SourceFormat::of_source(source)? {
+        SourceFormat::Zip => newest_entry_time(source)?.map(FileTime::WallClock),
+        SourceFormat::Tar(compression) => {
+            let mut newest: Option<u64> = None;
+            for entry in open_tar(source, compression)?.entries()? {
+                let entry = entry?;
+                if entry.header().entry_type().is_file() {
+                    newest = newest.max(Some(entry.header().mtime()?));
+                }
+            }
+            newest
+                .and_then(|seconds| DateTime::from_timestamp(i64::try_from(seconds).ok()?, 0))
+                .map(FileTime::Instant)
+        }
+        SourceFormat::Directory => {
+            let mut newest: Option<DateTime<Utc>> = None;
+            for entry in folder_entries(source) {
+                let entry = entry?;
+                if entry.file_type().is_file() {
+                    newest = newest.max(Some(entry.metadata()?.modified()?.into()));
+                }
+            }
+            newest.map(FileTime::Instant)
+        }
+    };
+    Ok(newest)
+}
+
+/// Newest modification time of the files of a zip, as the wall-clock time stored by the zip
+fn newest_entry_time(zip_file: &Path) -> Result<Option<NaiveDateTime>, zip::result::ZipError> {
+    let mut archive = zip::ZipArchive::new(File::open(zip_file)?)?;
+    let mut newest: Option<NaiveDateTime> = None;
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let modified = entry.last_modified();
+        let time = chrono::NaiveDate::from_ymd_opt(
+            modified.year().into(),
+            modified.month().into(),
+            modified.day().into(),
+        )
+        .and_then(|date| {
+            date.and_hms_opt(
+                modified.hour().into(),
+                modified.minute().into(),
+                modified.second().into(),
+            )
+        });
+        newest = newest.max(time);
+    }
+    Ok(newest)
+}
+
+/// Replace the working tree of `extract_dir`, `.git` aside, with the project found in the
+/// sources. An archive is unpacked in `temp_path` first, a folder is copied as it is.
+pub fn extract_source_to_repo(
+    source: &Path,
+    extract_dir: &Path,
+    temp_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let format = SourceFormat::of_source(source)?;
+    let unpacked = match format {
+        SourceFormat::Directory => source,
+        SourceFormat::Zip => {
+            std::fs::create_dir_all(temp_path)?;
+            extract_zip(source, temp_path)?;
+            temp_path
+        }
+        SourceFormat::Tar(compression) => {
+            std::fs::create_dir_all(temp_path)?;
+            open_tar(source, compression)?.unpack(temp_path)?;
+            temp_path
+        }
+    };
+    let project_root_path = get_project_root(unpacked)?;
+    println!("Project root found at: {}", project_root_path.display());
+    delete_folder_contents_except_git(extract_dir)?;
+    copy_dir_all(&project_root_path, extract_dir)?;
+
+    if format != SourceFormat::Directory {
+        std::fs::remove_dir_all(temp_path)?;
+    }
+
+    Ok(())
+}
+
+/// Unpack every entry of a zip as its files are read in place: symlinks stay symlinks
+fn extract_zip(zip_file: &Path, directory: &Path) -> io::Result<()> {
+    let mut archive = zip::ZipArchive::new(File::open(zip_file)?)?;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let Some(path) = entry.enclosed_name().map(|path| directory.join(path)) else {
+            continue;
+        };
+        if entry.is_dir() {
+            std::fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        match entry.unix_mode() {
+            Some(mode) if mode & UNIX_FILE_TYPE == UNIX_SYMLINK => {
+                let mut target = Vec::new();
+                entry.read_to_end(&mut target)?;
+                create_symlink(&target, &path)?;
+            }
+            mode => {
+                io::copy(&mut entry, &mut File::create(&path)?)?;
+                if let Some(mode) = mode {
+                    set_permissions(&path, mode & 0o777)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn open_tar(tar_file: &Path, compression: Compression) -> io::Result<tar::Archive<Box<dyn Read>>> {
+    let file = BufReader::new(File::open(tar_file)?);
+    let reader: Box<dyn Read> = match compression {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        Compression::Xz => Box::new(xz2::read::XzDecoder::new(file)),
+        Compression::Bzip2 => Box::new(bzip2::read::BzDecoder::new(file)),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// Files and folders of a folder of sources, sorted by name, `.git` aside
+fn folder_entries(folder: &Path) -> impl Iterator<Item = walkdir::Result<walkdir::DirEntry>> {
+    WalkDir::new(folder)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != ".git")
+}
+
+/// Path components of the entries of a zip, and whether the entry is a folder
+fn entry_paths(archive: &mut zip::ZipArchive<File>) -> Vec<(Vec<String>, bool)> {
+    (0..archive.len())
+        .filter_map(|index| {
+            let entry = archive.by_index_raw(index).ok()?;
+            Some((components(entry.enclosed_name()?)?, entry.is_dir()))
+        })
+        .collect()
+}
+
+/// Names of the folders and file of a relative path, `None` for a path going up or for no path
+/// at all. A leading `/` is dropped, as when the archive is unpacked.
+fn components(path: &Path) -> Option<Vec<String>> {
+    let mut names = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => names.push(name.to_string_lossy().into_owned()),
+            Component::ParentDir => return None,
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    (!names.is_empty()).then_some(names)
+}
+
+/// Number of leading folders to drop, as `get_project_root` does on the unpacked archive:
+/// go down the folders holding nothing but a single sub-folder
+fn project_root_depth(paths: &[(Vec<String>, bool)]) -> usize {
+    let mut depth = 0;
+    loop {
+        let below: Vec<&(Vec<String>, bool)> = paths
+            .iter()
+            .filter(|(path, _)| path.len() > depth)
+            .collect();
+        let child = match below.first() {
+            Some((path, _)) => &path[depth],
+            None => return depth,
+        };
+        let single_folder = below
+            .iter()
+            .all(|(path, is_dir)| &path[depth] == child && (path.len() > depth + 1 || *is_dir));
+        if !single_folder {
+            return depth;
+        }
+        depth += 1;
+    }
+}
+
+/// Call `visit` with the path below the project root, the git mode and the content of every file
+/// of the sources, nothing is extracted
+pub fn for_each_project_file(
+    source: &Path,
+    visit: impl FnMut(&[String], i32, &mut dyn Read) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    match SourceFormat::of_source(source)? {
+        SourceFormat::Zip => for_each_zip_file(source, visit),
+        SourceFormat::Tar(compression) => for_each_tar_file(source, compression, visit),
+        SourceFormat::Directory => for_each_folder_file(source, visit),
+    }
+}
+
+fn for_each_zip_file(
+    zip_file: &Path,
+    mut visit: impl FnMut(&[String], i32, &mut dyn Read) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(File::open(zip_file)?)?;
+    let depth = project_root_depth(&entry_paths(&mut archive));
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let path = match entry.enclosed_name() {
+            Some(path) if !entry.is_dir() => match components(path) {
+                Some(path) => path,
+                None => continue,
+            },
+            _ => continue,
+        };
+        let mode = match entry.unix_mode() {
+            Some(mode) if mode & UNIX_FILE_TYPE == UNIX_SYMLINK => SYMLINK,
+            Some(mode) => file_mode(mode),
+            None => REGULAR_FILE,
+        };
+        // the project root holds every file, below it
+        visit(&path[depth..], mode, &mut entry)?;
+    }
+    Ok(())
+}
+
+/// A tar archive is read twice: its entries come as a stream, the project root must be known
+/// before the first file. A hard link is a copy of the file it links to, as once unpacked.
+fn for_each_tar_file(
+    tar_file: &Path,
+    compression: Compression,
+    mut visit: impl FnMut(&[String], i32, &mut dyn Read) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut paths: Vec<(Vec<String>, bool)> = Vec::new();
+    // Files some hard link links to, kept once read
+    let mut linked: HashMap<Vec<String>, Option<(i32, Vec<u8>)>> = HashMap::new();
+    for entry in open_tar(tar_file, compression)?.entries()? {
+        let entry = entry?;
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_hard_link() {
+            if let Some(target) = entry.link_name()?.as_deref().and_then(components) {
+                linked.insert(target, None);
+            }
+        }
+        if entry_type.is_file()
+            || entry_type.is_symlink()
+            || entry_type.is_hard_link()
+            || entry_type.is_dir()
+        {
+            if let Some(path) = components(&entry.path()?) {
+                paths.push((path, entry_type.is_dir()));
+            }
+        }
+    }
+    let depth = project_root_depth(&paths);
+    for entry in open_tar(tar_file, compression)?.entries()? {
+        let mut entry = entry?;
+        let path = match components(&entry.path()?) {
+            Some(path) => path,
+            None => continue,
+        };
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() {
+            let target = entry.link_name_bytes().unwrap_or_default().into_owned();
+            visit(&path[depth..], SYMLINK, &mut target.as_slice())?;
+        } else if entry_type.is_hard_link() {
+            let target = entry.link_name()?.as_deref().and_then(components);
+            if let Some(Some((mode, content))) = target.and_then(|target| linked.get(&target)) {
+                visit(&path[depth..], *mode, &mut content.as_slice())?;
+            }
+        } else if entry_type.is_file() {
+            let mode = file_mode(entry.header().mode()?);
+            match linked.get_mut(&path) {
+                Some(kept) => {
+                    let mut content = Vec::new();
+                    entry.read_to_end(&mut content)?;
+                    visit(&path[depth..], mode, &mut content.as_slice())?;
+                    *kept = Some((mode, content));
+                }
+                None => visit(&path[depth..], mode, &mut entry)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The project root of a folder is found as when it is copied over the working tree
+fn for_each_folder_file(
+    folder: &Path,
+    mut visit: impl FnMut(&[String], i32, &mut dyn Read) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let project_root = get_project_root(folder)?;
+    for entry in folder_entries(&project_root) {
+        let entry = entry?;
+        let path = match components(entry.path().strip_prefix(&project_root)?) {
+            Some(path) => path,
+            None => continue,
+        };
+        if entry.file_type().is_symlink() {
+            let target = link_target(entry.path())?;
+            visit(&path, SYMLINK, &mut target.as_slice())?;
+        } else if entry.file_type().is_file() {
+            let mode = file_mode(unix_mode(&entry.metadata()?));
+            visit(&path, mode, &mut File::open(entry.path())?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Git mode of a file from its unix permissions: executable when its owner may run it, as git
+/// reads a working tree
+fn file_mode(unix_mode: u32) -> i32 {
+    match unix_mode & 0o100 {
+        0 => REGULAR_FILE,
+        _ => EXECUTABLE_FILE,
+    }
+}
+
+#[cfg(unix)]
+fn unix_mode(metadata: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn unix_mode(_metadata: &std::fs::Metadata) -> u32 {
+    0o644
+}
+
+#[cfg(unix)]
+fn set_permissions(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_permissions(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Path a symlink points to, as git stores it
+#[cfg(unix)]
+fn link_target(link: &Path) -> io::Result<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(std::fs::read_link(link)?.as_os_str().as_bytes().to_vec())
+}
+
+#[cfg(not(unix))]
+fn link_target(link: &Path) -> io::Result<Vec<u8>> {
+    Ok(std::fs::read_link(link)?
+        .to_string_lossy()
+        .into_owned()
+        .into_bytes())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &[u8], link: &Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(target), link)
+}
+
+/// Without symlinks, the file holds the path it points to, as git checks it out
+#[cfg(not(unix))]
+fn create_symlink(target: &[u8], link: &Path) -> io::Result<()> {
+    std::fs::write(link, target)
+}
+
+/// Go down the folders holding nothing but a single sub-folder, the project starts where they
+/// branch out or hold a file
+pub fn get_project_root(dir_path: &Path) -> Result<PathBuf, std::io::Error> {
+    let mut current_path = dir_path.to_path_buf();
+
+    loop {
+        let entries: Vec<std::fs::DirEntry> = std::fs::read_dir(&current_path)?
+            .filter_map(|entry| entry.ok()) // Filter out potential errors
+            .collect();
+
+        // A symlink to a folder is a file of the project
+        match entries.as_slice() {
+            [entry] if entry.file_type()?.is_dir() => {
+                // Update the current path to the single child folder found
+                current_path = entry.path();
+            }
+            _ => break, // Break the loop at the first file or at more than one folder
+        }
+    }
+
+    Ok(current_path)
+}
+
+/// Copy the content of `src` into `dst`, `.git` folders aside. Symlinks are copied as symlinks,
+/// not followed.
+pub fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> std::io::Result<()> {
+    std::fs::create_dir_all(&dst)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let ty = entry.file_type()?;
+        if entry.file_name() != ".git" {
+            if ty.is_dir() {
+                copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
+            } else if ty.is_symlink() {
+                create_symlink(
+                    &link_target(&entry.path())?,
+                    &dst.as_ref().join(entry.file_name()),
+                )?;
+            } else {
+                std::fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Delete the folder and everything in it
+pub fn delete_folder(path: &Path) -> std::io::Result<()> {
+    std::fs::remove_dir_all(path)?;
+    Ok(())
+}
+
+/// Empty the folder, `.git` aside
+pub fn delete_folder_contents_except_git(path: &Path) -> Result<(), std::io::Error> {
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let file_path = entry.path();
+
+            if file_path.file_name() != Some(std::ffi::OsStr::new(".git")) {
+                if file_type.is_dir() {
+                    delete_folder_contents_except_git(&file_path)?;
+                    std::fs::remove_dir(&file_path)?;
+                } else {
+                    std::fs::remove_file(&file_path)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
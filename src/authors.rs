@@ -0,0 +1,124 @@
+//! Git identity of the authors of the exports, from a mailmap-style file.
+//!
+//! Each line of the file maps a name written in the exports to a canonical identity:
+//!
+//! ```text
+//! # canonical name <e-mail> [name or initials in the exports]
+//! Jean Dupont <jean.dupont@allianz.com>
+//! Jean Dupont <jean.dupont@allianz.com> JD
+//! Jean Dupont <jean.dupont@allianz.com> Dupont Jean
+//! ```
+//!
+//! Names are compared without accents, case, punctuation or repeated spaces. An author missing
+//! from the file keeps their name and gets an e-mail built from it on the fallback domain.
+
+use crate::get_path;
+use deunicode::deunicode;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+#[derive(Clone)]
+pub struct Identity {
+    pub name: String,
+    pub email: String,
+}
+
+pub struct AuthorMap {
+    /// Canonical identity of every normalized name and alias
+    identities: HashMap<String, Identity>,
+    fallback_domain: String,
+}
+
+impl AuthorMap {
+    /// No mapping: every author gets an e-mail on `fallback_domain`
+    pub fn new(fallback_domain: &str) -> Self {
+        AuthorMap {
+            identities: HashMap::new(),
+            fallback_domain: fallback_domain.to_owned(),
+        }
+    }
+
+    pub fn load(path: &Path, fallback_domain: &str) -> Result<Self, Box<dyn Error>> {
+        let path = get_path(path);
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Cannot read authors file {}: {}", path.display(), e))?;
+        Self::parse(&content, fallback_domain)
+            .map_err(|e| format!("Invalid authors file {}: {}", path.display(), e).into())
+    }
+
+    pub fn parse(content: &str, fallback_domain: &str) -> Result<Self, String> {
+        let mut authors = AuthorMap::new(fallback_domain);
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (identity, alias) = parse_line(line)
+                .ok_or_else(|| format!("line {}: expected `Name <e-mail> [alias]`", index + 1))?;
+            for name in [Some(identity.name.as_str()), alias].into_iter().flatten() {
+                let key = normalize(name);
+                match authors.identities.get(&key) {
+                    Some(known) if known.email != identity.email => {
+                        return Err(format!(
+                            "line {}: {} is already mapped to <{}>",
+                            index + 1,
+                            name,
+                            known.email
+                        ))
+                    }
+                    _ => {
+                        authors.identities.insert(key, identity.clone());
+                    }
+                }
+            }
+        }
+        Ok(authors)
+    }
+
+    /// Identity of `author`, `None` when the file doesn't map it
+    pub fn find(&self, author: &str) -> Option<&Identity> {
+        self.identities.get(&normalize(author))
+    }
+
+    /// Identity of `author`, built from the name on the fallback domain when it isn't mapped
+    pub fn resolve(&self, author: &str) -> Identity {
+        match self.find(author) {
+            Some(identity) => identity.clone(),
+            None => Identity {
+                name: author.split_whitespace().collect::<Vec<_>>().join(" "),
+                email: format!("{}@{}", email_local_part(author), self.fallback_domain),
+            },
+        }
+    }
+}
+
+/// `Name <e-mail> [alias]`
+fn parse_line(line: &str) -> Option<(Identity, Option<&str>)> {
+    let (name, rest) = line.split_once('<')?;
+    let (email, alias) = rest.split_once('>')?;
+    let (name, email, alias) = (name.trim(), email.trim(), alias.trim());
+    if name.is_empty() || !email.contains('@') {
+        return None;
+    }
+    let identity = Identity {
+        name: name.to_owned(),
+        email: email.to_owned(),
+    };
+    Some((identity, (!alias.is_empty()).then_some(alias)))
+}
+
+/// Key names are compared on: no accents, lowercase, letters and digits separated by one space
+pub fn normalize(name: &str) -> String {
+    deunicode(name)
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `Zoé Martin` → `zoemartin`
+fn email_local_part(name: &str) -> String {
+    normalize(name).replace(' ', "")
+}
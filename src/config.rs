@@ -0,0 +1,548 @@
+//! Migration file and settings of both steps: overrides (the command line flags), then the
+//! migration file, then the defaults.
+
+use crate::archive::DEFAULT_ZIP_NAME_PATTERN;
+use crate::authors::AuthorMap;
+use crate::duplicates::DuplicatePolicy;
+use crate::get_path;
+use crate::ingest::{is_supported_workbook, DateSystem, LogicielType, WORKBOOK_EXTENSIONS};
+use crate::lineage::CyclePolicy;
+use crate::message::{MessageTemplate, DEFAULT_SUBJECT, DEFAULT_WIDTH};
+use crate::plan::{DateSource, NoZipPolicy, DATE_SOURCES};
+use crate::push::Backend;
+use chrono_tz::Tz;
+use regex::Regex;
+use serde::Deserialize;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Migration file, see `migration.example.toml`. A misspelt section is an error, not a
+/// section left out.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct MigrationFile {
+    #[serde(default)]
+    pub prepare: PrepareConfig,
+    #[serde(default)]
+    pub push: PushConfig,
+}
+
+/// `[prepare]` section of the migration file
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PrepareConfig {
+    #[serde(default)]
+    pub workbooks: Vec<WorkbookConfig>,
+    pub target_filter: Option<String>,
+    pub csv_dir: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    pub linked_lists: Option<bool>,
+    pub on_cycle: Option<CyclePolicy>,
+    pub on_duplicate: Option<DuplicatePolicy>,
+    pub preferred_source: Option<String>,
+}
+
+/// One `[[prepare.workbooks]]` entry
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkbookConfig {
+    pub path: PathBuf,
+    pub logiciel_type: LogicielType,
+    /// Columns removed once the `Reference` column is built, defaults depend on `logiciel_type`
+    pub drop_columns: Option<Vec<String>>,
+    /// `"1900"` or `"1904"`, read from the workbook when left out
+    pub date_system: Option<DateSystem>,
+}
+
+/// `[push]` section of the migration file
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PushConfig {
+    pub repo_path: Option<PathBuf>,
+    pub parquets_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub base_references: Vec<String>,
+    #[serde(default)]
+    pub zip_folders: Vec<PathBuf>,
+    pub default_zip: Option<PathBuf>,
+    pub temp_dir: Option<PathBuf>,
+    pub author_email_domain: Option<String>,
+    pub authors: Option<PathBuf>,
+    pub incremental: Option<bool>,
+    pub time_zone: Option<String>,
+    /// Sources of the commit dates, by priority
+    #[serde(default)]
+    pub commit_date: Vec<DateSource>,
+    #[serde(default)]
+    pub message: MessageConfig,
+    pub backend: Option<Backend>,
+    /// Create a bare repository, the odb and fast-import backends need no working tree
+    pub bare: Option<bool>,
+    /// Write the stream of the fast-import backend to this file instead of the repository
+    pub fast_import_output: Option<PathBuf>,
+    pub no_zip: Option<NoZipPolicy>,
+    /// Regex over the file names of the zips, its `reference` group gives the version
+    pub zip_name_pattern: Option<String>,
+}
+
+/// `[push.message]` section of the migration file, see `message` for the placeholders
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct MessageConfig {
+    pub subject: Option<String>,
+    pub body: Option<String>,
+    #[serde(default)]
+    pub trailers: Vec<String>,
+    pub subject_width: Option<usize>,
+    pub body_width: Option<usize>,
+}
+
+impl MigrationFile {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let path = get_path(path);
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Cannot read config file {}: {}", path.display(), e))?;
+        let file: MigrationFile = toml::from_str(&content)
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+        Ok(file)
+    }
+
+    /// The migration file at `path`, or an empty one when there is none
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        match path {
+            Some(path) => Self::load(path),
+            None => Ok(MigrationFile::default()),
+        }
+    }
+}
+
+pub struct Workbook {
+    pub path: PathBuf,
+    pub logiciel_type: LogicielType,
+    pub drop_columns: Vec<String>,
+    /// Date system given by the migration file or the command line, read from the workbook by
+    /// `PrepareSettings::resolve` if none
+    pub date_system: Option<DateSystem>,
+}
+
+impl Workbook {
+    /// Date system the serial dates of the workbook are read with, read from the workbook when
+    /// `PrepareSettings::resolve` hasn't already
+    pub fn date_system(&self) -> Result<DateSystem, Box<dyn Error>> {
+        match self.date_system {
+            Some(date_system) => Ok(date_system),
+            None => DateSystem::detect(&self.path),
+        }
+    }
+}
+
+/// Settings of a `prepare` run
+pub struct PrepareSettings {
+    pub workbooks: Vec<Workbook>,
+    pub target_filter: String,
+    /// Debug CSV dumps of the workbooks are only written when a folder is given
+    pub csv_dir: Option<PathBuf>,
+    pub output_dir: PathBuf,
+    /// Also write one .parquet file per linked list, as `<root>_<n>.parquet`
+    pub linked_lists: bool,
+    pub on_cycle: CyclePolicy,
+    pub on_duplicate: DuplicatePolicy,
+    /// File name of the workbook whose rows win with `DuplicatePolicy::PreferSource`
+    pub preferred_source: Option<String>,
+}
+
+impl LogicielType {
+    fn default_drop_columns(&self) -> Vec<String> {
+        self.reference_headers()
+            .iter()
+            .map(|column| column.to_string())
+            .collect()
+    }
+}
+
+impl From<WorkbookConfig> for Workbook {
+    fn from(workbook: WorkbookConfig) -> Self {
+        let path = get_path(&workbook.path);
+        Workbook {
+            date_system: workbook.date_system,
+            path,
+            drop_columns: workbook
+                .drop_columns
+                .unwrap_or_else(|| workbook.logiciel_type.default_drop_columns()),
+            logiciel_type: workbook.logiciel_type,
+        }
+    }
+}
+
+impl PrepareSettings {
+    /// Each key of `overrides` wins over the same key of `config`
+    pub fn resolve(
+        overrides: PrepareConfig,
+        config: PrepareConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        let workbooks = if !overrides.workbooks.is_empty() {
+            overrides
+                .workbooks
+                .into_iter()
+                .map(Workbook::from)
+                .collect()
+        } else if !config.workbooks.is_empty() {
+            config.workbooks.into_iter().map(Workbook::from).collect()
+        } else {
+            default_workbooks()
+        };
+
+        let mut settings = PrepareSettings {
+            workbooks,
+            target_filter: overrides
+                .target_filter
+                .or(config.target_filter)
+                .unwrap_or_else(|| "SD CT".to_owned()),
+            csv_dir: overrides.csv_dir.or(config.csv_dir).map(get_path),
+            output_dir: get_path(
+                overrides
+                    .output_dir
+                    .or(config.output_dir)
+                    .unwrap_or_else(|| PathBuf::from("./parquets")),
+            ),
+            linked_lists: overrides
+                .linked_lists
+                .or(config.linked_lists)
+                .unwrap_or(false),
+            on_cycle: overrides.on_cycle.or(config.on_cycle).unwrap_or_default(),
+            on_duplicate: overrides
+                .on_duplicate
+                .or(config.on_duplicate)
+                .unwrap_or_default(),
+            preferred_source: overrides.preferred_source.or(config.preferred_source),
+        };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Report every problem at once instead of failing half-way through the conversion. The date
+    /// system of each workbook is read here, once.
+    fn validate(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut problems: Vec<String> = Vec::new();
+        if self.workbooks.is_empty() {
+            problems.push("no workbook to read".to_owned());
+        }
+        for workbook in &mut self.workbooks {
+            if !workbook.path.is_file() {
+                problems.push(format!("workbook {} not found", workbook.path.display()));
+            } else if !is_supported_workbook(&workbook.path) {
+                problems.push(format!(
+                    "workbook {} is not a .{} file",
+                    workbook.path.display(),
+                    WORKBOOK_EXTENSIONS.join(", .")
+                ));
+            } else {
+                match workbook.date_system() {
+                    Ok(date_system) => workbook.date_system = Some(date_system),
+                    Err(_) => problems.push(format!(
+                        "cannot read the date system of workbook {}, set its date_system",
+                        workbook.path.display()
+                    )),
+                }
+            }
+            if workbook.drop_columns.iter().any(|column| column.is_empty()) {
+                problems.push(format!(
+                    "workbook {} has an empty column name in drop_columns",
+                    workbook.path.display()
+                ));
+            }
+        }
+        match &self.preferred_source {
+            None if self.on_duplicate == DuplicatePolicy::PreferSource => {
+                problems.push("on_duplicate is prefer-source but no preferred_source".to_owned());
+            }
+            Some(source)
+                if !self.workbooks.iter().any(|workbook| {
+                    workbook.path.file_name() == Some(std::ffi::OsStr::new(source))
+                }) =>
+            {
+                problems.push(format!(
+                    "preferred_source {} is not the file name of a workbook",
+                    source
+                ));
+            }
+            _ => {}
+        }
+        if self.target_filter.is_empty() {
+            problems.push("target_filter is empty".to_owned());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Invalid prepare settings: {}", problems.join("; ")).into())
+        }
+    }
+}
+
+fn default_workbooks() -> Vec<Workbook> {
+    [
+        ("./xls/08122023_Logiciel_codifié.xls", LogicielType::Code),
+        (
+            "./xls/08122023_CT_Codified_Software.xls",
+            LogicielType::Code,
+        ),
+        ("./xls/08122023_Logiciel_Etude.xls", LogicielType::Etude),
+    ]
+    .into_iter()
+    .map(|(path, logiciel_type)| Workbook {
+        date_system: None,
+        path: get_path(path),
+        drop_columns: logiciel_type.default_drop_columns(),
+        logiciel_type,
+    })
+    .collect()
+}
+
+/// Where the tables are read and which repository they describe
+pub struct TableSettings {
+    pub repo_path: PathBuf,
+    pub parquets_dir: PathBuf,
+    pub base_references: Vec<String>,
+}
+
+/// Settings of a `push` run
+pub struct PushSettings {
+    pub tables: TableSettings,
+    pub zip_folders: Vec<PathBuf>,
+    pub default_zip: Option<PathBuf>,
+    pub temp_dir: PathBuf,
+    /// Domain of the e-mails of the authors missing from `authors`
+    pub author_email_domain: String,
+    /// Mailmap-style file mapping the author names of the exports to git identities
+    pub authors: Option<PathBuf>,
+    /// Keep the repository and only commit the versions without a tag
+    pub incremental: bool,
+    pub message: MessageTemplate,
+    /// Time zone of the dates of the exports, its offset at each date goes into the commits
+    pub time_zone: Tz,
+    /// Sources of the commit dates, the first one knowing the date of a version wins
+    pub commit_date: Vec<DateSource>,
+    /// How the sources of the versions are written into the repository
+    pub backend: Backend,
+    pub bare: bool,
+    pub fast_import_output: Option<PathBuf>,
+    /// What becomes of a version without a zip of its own
+    pub no_zip: NoZipPolicy,
+    /// Gives the reference of a zip from its file name, in its `reference` group
+    pub zip_name_pattern: Regex,
+}
+
+impl TableSettings {
+    /// Each key of `overrides` wins over the same key of `config`
+    pub fn resolve(overrides: &PushConfig, config: &PushConfig) -> Result<Self, Box<dyn Error>> {
+        let settings = Self::merge(overrides, config);
+        let mut problems = Vec::new();
+        settings.check(&mut problems);
+        report(problems)?;
+        Ok(settings)
+    }
+
+    fn merge(overrides: &PushConfig, config: &PushConfig) -> Self {
+        let base_references = if !overrides.base_references.is_empty() {
+            overrides.base_references.clone()
+        } else if !config.base_references.is_empty() {
+            config.base_references.clone()
+        } else {
+            vec!["B13264R-A".to_owned()]
+        };
+        TableSettings {
+            repo_path: get_path(
+                overrides
+                    .repo_path
+                    .clone()
+                    .or_else(|| config.repo_path.clone())
+                    .unwrap_or_else(|| PathBuf::from("../legacy-to-git")),
+            ),
+            parquets_dir: get_path(
+                overrides
+                    .parquets_dir
+                    .clone()
+                    .or_else(|| config.parquets_dir.clone())
+                    .unwrap_or_else(|| PathBuf::from("./parquets")),
+            ),
+            base_references,
+        }
+    }
+
+    fn check(&self, problems: &mut Vec<String>) {
+        if !self.parquets_dir.is_dir() {
+            problems.push(format!(
+                "parquets folder {} not found",
+                self.parquets_dir.display()
+            ));
+        }
+        if self
+            .base_references
+            .iter()
+            .any(|reference| reference.trim().is_empty())
+        {
+            problems.push("base_references contains an empty reference".to_owned());
+        }
+    }
+}
+
+impl PushSettings {
+    /// Each key of `overrides` wins over the same key of `config`
+    pub fn resolve(overrides: PushConfig, config: PushConfig) -> Result<Self, Box<dyn Error>> {
+        let tables = TableSettings::merge(&overrides, &config);
+        let zip_folders = if !overrides.zip_folders.is_empty() {
+            overrides.zip_folders
+        } else if !config.zip_folders.is_empty() {
+            config.zip_folders
+        } else {
+            vec![PathBuf::from("./zips")]
+        };
+        let (message, config_message) = (overrides.message, config.message);
+        let trailers = if !message.trailers.is_empty() {
+            message.trailers
+        } else {
+            config_message.trailers
+        };
+        let message = MessageTemplate::new(
+            &message
+                .subject
+                .or(config_message.subject)
+                .unwrap_or_else(|| DEFAULT_SUBJECT.to_owned()),
+            message.body.or(config_message.body).as_deref(),
+            &trailers,
+            message
+                .subject_width
+                .or(config_message.subject_width)
+                .unwrap_or(DEFAULT_WIDTH),
+            message
+                .body_width
+                .or(config_message.body_width)
+                .unwrap_or(DEFAULT_WIDTH),
+        )
+        .map_err(|e| format!("Invalid push settings: {}", e))?;
+        let time_zone = overrides.time_zone.or(config.time_zone);
+        let time_zone = match time_zone.as_deref() {
+            Some(name) => name.parse::<Tz>().map_err(|_| {
+                format!(
+                    "Invalid push settings: time_zone `{}` is not an IANA time zone such as Europe/Paris",
+                    name
+                )
+            })?,
+            None => Tz::UTC,
+        };
+        let zip_name_pattern = overrides
+            .zip_name_pattern
+            .or(config.zip_name_pattern)
+            .unwrap_or_else(|| DEFAULT_ZIP_NAME_PATTERN.to_owned());
+        let zip_name_pattern = Regex::new(&zip_name_pattern)
+            .ok()
+            .filter(|regex| regex.capture_names().any(|name| name == Some("reference")))
+            .ok_or_else(|| {
+                format!(
+                    "Invalid push settings: zip_name_pattern `{}` is not a regex with a `(?P<reference>...)` group",
+                    zip_name_pattern
+                )
+            })?;
+        let settings = PushSettings {
+            tables,
+            zip_folders: zip_folders.iter().map(get_path).collect(),
+            default_zip: overrides.default_zip.or(config.default_zip).map(get_path),
+            temp_dir: get_path(
+                overrides
+                    .temp_dir
+                    .or(config.temp_dir)
+                    .unwrap_or_else(|| PathBuf::from("./temp")),
+            ),
+            author_email_domain: overrides
+                .author_email_domain
+                .or(config.author_email_domain)
+                .unwrap_or_else(|| "allianz.com".to_owned()),
+            authors: overrides.authors.or(config.authors).map(get_path),
+            incremental: overrides
+                .incremental
+                .or(config.incremental)
+                .unwrap_or(false),
+            message,
+            time_zone,
+            commit_date: if !overrides.commit_date.is_empty() {
+                overrides.commit_date
+            } else if !config.commit_date.is_empty() {
+                config.commit_date
+            } else {
+                DATE_SOURCES.to_vec()
+            },
+            backend: overrides.backend.or(config.backend).unwrap_or_default(),
+            bare: overrides.bare.or(config.bare).unwrap_or(false),
+            fast_import_output: overrides
+                .fast_import_output
+                .or(config.fast_import_output)
+                .map(get_path),
+            no_zip: overrides.no_zip.or(config.no_zip).unwrap_or_default(),
+            zip_name_pattern,
+        };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Report every problem at once instead of failing in the middle of the import
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let mut problems = Vec::new();
+        self.tables.check(&mut problems);
+        for folder in &self.zip_folders {
+            if !folder.is_dir() {
+                problems.push(format!("zip folder {} not found", folder.display()));
+            }
+        }
+        if let Some(default_zip) = &self.default_zip {
+            if !default_zip.is_file() {
+                problems.push(format!("default zip {} not found", default_zip.display()));
+            }
+        }
+        if let Some(authors) = &self.authors {
+            if !authors.is_file() {
+                problems.push(format!("authors file {} not found", authors.display()));
+            }
+        }
+        if self.bare && self.backend == Backend::Worktree {
+            problems.push(
+                "bare = true needs backend = \"odb\" or \"fast-import\", the worktree backend commits a checkout"
+                    .to_owned(),
+            );
+        }
+        if self.fast_import_output.is_some() && self.backend != Backend::FastImport {
+            problems.push("fast_import_output needs backend = \"fast-import\"".to_owned());
+        }
+        if self.author_email_domain.is_empty() || self.author_email_domain.contains('@') {
+            problems.push(format!(
+                "author_email_domain `{}` must be a bare domain such as allianz.com",
+                self.author_email_domain
+            ));
+        }
+        report(problems)
+    }
+
+    /// Identities of the authors, every author is unmapped without an authors file
+    pub fn author_map(&self) -> Result<AuthorMap, Box<dyn Error>> {
+        match &self.authors {
+            Some(path) => AuthorMap::load(path, &self.author_email_domain),
+            None => Ok(AuthorMap::new(&self.author_email_domain)),
+        }
+    }
+
+    /// Zip used when a version of `base_reference` has no zip of its own
+    pub fn default_zip_for(&self, base_reference: &str) -> PathBuf {
+        match &self.default_zip {
+            Some(default_zip) => default_zip.clone(),
+            None => self.zip_folders[0].join(format!("Sources {}.zip", base_reference)),
+        }
+    }
+}
+
+fn report(problems: Vec<String>) -> Result<(), Box<dyn Error>> {
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Invalid push settings: {}", problems.join("; ")).into())
+    }
+}
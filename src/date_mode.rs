@@ -0,0 +1,173 @@
+//! The date system declared inside a workbook, read from the bytes of each format without
+//! parsing the rest of the workbook. Every reader returns `None` on input it cannot make sense
+//! of, truncated or malformed, and never panics.
+
+/// Whether `xl/workbook.xml` of an .xlsx or .xlsm workbook sets `date1904`
+pub fn xlsx_declares_1904(workbook_xml: &[u8]) -> bool {
+    let workbook_xml = String::from_utf8_lossy(workbook_xml);
+    workbook_xml.contains("date1904=\"1\"") || workbook_xml.contains("date1904=\"true\"")
+}
+
+/// Whether `content.xml` of an .ods spreadsheet counts its dates from 1904-01-01, its null date
+/// is 1899-12-30 by default
+pub fn ods_declares_1904(content_xml: &[u8]) -> bool {
+    String::from_utf8_lossy(content_xml).contains("table:date-value=\"1904-01-01\"")
+}
+
+/// Whether the `BrtWbProp` record of an .xlsb workbook sets `f1904`
+pub fn xlsb_declares_1904(records: &[u8]) -> Option<bool> {
+    const BRT_WB_PROP: usize = 0x0099;
+    // Types take up to 2 bytes and sizes up to 4, 7 bits per byte and the high bit to go on
+    let varint = |offset: &mut usize, max_bytes: usize| -> Option<usize> {
+        let mut value = 0;
+        for shift in 0..max_bytes {
+            let byte = *records.get(*offset)?;
+            *offset += 1;
+            value |= ((byte & 0x7F) as usize) << (7 * shift);
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        Some(value)
+    };
+    let mut offset = 0;
+    while offset < records.len() {
+        let kind = varint(&mut offset, 2)?;
+        let size = varint(&mut offset, 4)?;
+        let data = records.get(offset..offset + size)?;
+        if kind == BRT_WB_PROP {
+            return Some(data.first()? & 0x1 != 0);
+        }
+        offset += size;
+    }
+    Some(false)
+}
+
+/// Whether the `DATEMODE` record of the workbook stream of an .xls file is 1
+pub fn xls_declares_1904(bytes: &[u8]) -> Option<bool> {
+    const DATEMODE: u16 = 0x0022;
+    const EOF: u16 = 0x000A;
+    let stream = compound_file_stream(bytes, &["Workbook", "Book"])?;
+    // BIFF records of the workbook globals: type, size and data, up to their EOF record
+    let mut offset = 0;
+    loop {
+        let kind = le_u16(&stream, offset)?;
+        let size = le_u16(&stream, offset + 2)? as usize;
+        match kind {
+            DATEMODE => return Some(le_u16(&stream, offset + 4)? == 1),
+            EOF => return Some(false),
+            _ => offset += 4 + size,
+        }
+    }
+}
+
+/// Content of the first stream named one of `names` in an OLE2 compound file, as .xls files are
+fn compound_file_stream(bytes: &[u8], names: &[&str]) -> Option<Vec<u8>> {
+    const SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+    const LAST_SECTOR: u32 = 0xFFFF_FFFA;
+    if bytes.get(..8)? != SIGNATURE {
+        return None;
+    }
+    // Sectors of 512 or 4096 bytes, mini sectors of 64
+    let sector_size = match le_u16(bytes, 0x1E)? {
+        9 => 512,
+        12 => 4096,
+        _ => return None,
+    };
+    const MINI_SECTOR_SIZE: usize = 64;
+    let sector = |id: u32| {
+        let start = (id as usize + 1).checked_mul(sector_size)?;
+        bytes.get(start..start + sector_size)
+    };
+
+    // The sectors of the FAT are listed by the header, then by a chain of DIFAT sectors
+    let mut fat_sectors: Vec<u32> = (0..109)
+        .filter_map(|i| le_u32(bytes, 0x4C + 4 * i))
+        .collect();
+    let mut difat_sector = le_u32(bytes, 0x44)?;
+    let difat_count = le_u32(bytes, 0x48)? as usize;
+    if difat_count > bytes.len() / sector_size {
+        return None;
+    }
+    for _ in 0..difat_count {
+        let difat = sector(difat_sector)?;
+        let entries = sector_size / 4 - 1;
+        fat_sectors.extend((0..entries).filter_map(|i| le_u32(difat, 4 * i)));
+        difat_sector = le_u32(difat, 4 * entries)?;
+    }
+    let fat: Vec<u32> = fat_sectors
+        .into_iter()
+        .filter(|&id| id <= LAST_SECTOR)
+        .map(&sector)
+        .collect::<Option<Vec<&[u8]>>>()?
+        .into_iter()
+        .flat_map(|sector| sector.chunks_exact(4))
+        .map(|id| le_u32(id, 0))
+        .collect::<Option<Vec<u32>>>()?;
+
+    // Directory entries of 128 bytes: UTF-16 name, type, first sector and size
+    let directory = sector_chain(&fat, le_u32(bytes, 0x30)?, sector)?;
+    let entries: Vec<&[u8]> = directory.chunks_exact(128).collect();
+    let root = entries.first()?;
+    let entry = entries.iter().find(|entry| {
+        let name_size = le_u16(entry, 0x40).unwrap_or(0) as usize;
+        let name: Vec<u16> = entry[..name_size.saturating_sub(2).min(64)]
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        const STREAM: u8 = 2;
+        entry[0x42] == STREAM && names.contains(&String::from_utf16_lossy(&name).as_str())
+    })?;
+    let start = le_u32(entry, 0x74)?;
+    let size = le_u32(entry, 0x78)? as usize;
+
+    // Streams under the cutoff are in the mini stream, the content of the root entry
+    let mut content = if size < le_u32(bytes, 0x38)? as usize {
+        let mini_fat: Vec<u32> = sector_chain(&fat, le_u32(bytes, 0x3C)?, sector)?
+            .chunks_exact(4)
+            .map(|id| le_u32(id, 0))
+            .collect::<Option<Vec<u32>>>()?;
+        let mini_stream = sector_chain(&fat, le_u32(root, 0x74)?, sector)?;
+        let mini_sector = |id: u32| {
+            let start = (id as usize).checked_mul(MINI_SECTOR_SIZE)?;
+            mini_stream.get(start..start + MINI_SECTOR_SIZE)
+        };
+        sector_chain(&mini_fat, start, mini_sector)?
+    } else {
+        sector_chain(&fat, start, sector)?
+    };
+    content.truncate(size);
+    Some(content)
+}
+
+/// Sectors from `start` on, each entry of `table` gives the sector that follows its own
+fn sector_chain<'a>(
+    table: &[u32],
+    start: u32,
+    sector: impl Fn(u32) -> Option<&'a [u8]>,
+) -> Option<Vec<u8>> {
+    const END_OF_CHAIN: u32 = 0xFFFF_FFFE;
+    let mut content = Vec::new();
+    let mut id = start;
+    // A chain longer than its table loops
+    for _ in 0..=table.len() {
+        if id == END_OF_CHAIN {
+            return Some(content);
+        }
+        content.extend_from_slice(sector(id)?);
+        id = *table.get(id as usize)?;
+    }
+    None
+}
+
+fn le_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
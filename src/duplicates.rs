@@ -0,0 +1,134 @@
+//! Keep one row per `Reference` when several workbooks export the same version.
+
+use crate::ingest::{EXCEL_ROW, SOURCE};
+use crate::lineage::describe_row;
+use clap::ValueEnum;
+use polars::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Workbook of the row kept for the same `Reference`, added to the audit table
+pub const KEPT_SOURCE: &str = "kept_source";
+/// Excel row of the row kept for the same `Reference`, added to the audit table
+pub const KEPT_EXCEL_ROW: &str = "kept_excel_row";
+
+/// Which row to keep when several rows share a `Reference`
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicatePolicy {
+    /// Keep the row with the latest Creation Date
+    #[default]
+    Latest,
+    /// Keep the row of the preferred source workbook, the latest one when none or several come
+    /// from it
+    PreferSource,
+    /// Stop and report the duplicates
+    Fail,
+}
+
+/// Table without duplicated references, and the rows left out with the location of the row kept
+/// in their place
+pub struct Deduplicated {
+    pub df: DataFrame,
+    pub audit: DataFrame,
+}
+
+/// `df` must be sorted from the most recent version to the oldest one, so that the first row of
+/// a `Reference` is the latest. Rows without `Reference` are never duplicates.
+pub fn resolve_duplicates(
+    df: &DataFrame,
+    policy: DuplicatePolicy,
+    preferred_source: Option<&str>,
+) -> Result<Deduplicated, Box<dyn Error>> {
+    let references: Vec<Option<&str>> = df.column("Reference")?.str()?.into_iter().collect();
+    let sources: Vec<Option<&str>> = df.column(SOURCE)?.str()?.into_iter().collect();
+
+    // Rows of each duplicated reference, in table order
+    let mut rows_by_reference: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut duplicated: Vec<&str> = Vec::new();
+    for (row, reference) in references.iter().enumerate() {
+        if let Some(reference) = reference {
+            let rows = rows_by_reference.entry(reference).or_default();
+            rows.push(row);
+            if rows.len() == 2 {
+                duplicated.push(reference);
+            }
+        }
+    }
+
+    if policy == DuplicatePolicy::Fail && !duplicated.is_empty() {
+        let report: Vec<String> = duplicated
+            .iter()
+            .map(|reference| {
+                let rows: Vec<String> = rows_by_reference[reference]
+                    .iter()
+                    .map(|&row| describe_row(df, &references, row))
+                    .collect();
+                rows.join(", ")
+            })
+            .collect();
+        return Err(format!(
+            "{} duplicated reference(s): {}",
+            duplicated.len(),
+            report.join("; ")
+        )
+        .into());
+    }
+
+    // (dropped row, kept row)
+    let mut dropped: Vec<(usize, usize)> = Vec::new();
+    for reference in &duplicated {
+        let rows = &rows_by_reference[reference];
+        let kept = match (policy, preferred_source) {
+            (DuplicatePolicy::PreferSource, Some(preferred)) => rows
+                .iter()
+                .copied()
+                .find(|&row| sources[row] == Some(preferred))
+                .unwrap_or(rows[0]),
+            _ => rows[0],
+        };
+        for &row in rows.iter().filter(|&&row| row != kept) {
+            dropped.push((row, kept));
+            println!(
+                "Duplicate {} is left out, {} is kept",
+                describe_row(df, &references, row),
+                describe_row(df, &references, kept)
+            );
+        }
+    }
+    dropped.sort_unstable();
+
+    let mut is_dropped: Vec<bool> = vec![false; df.height()];
+    for (row, _) in &dropped {
+        is_dropped[*row] = true;
+    }
+    let kept_rows: Vec<IdxSize> = (0..df.height())
+        .filter(|&row| !is_dropped[row])
+        .map(|row| row as IdxSize)
+        .collect();
+    let dropped_rows: Vec<IdxSize> = dropped.iter().map(|(row, _)| *row as IdxSize).collect();
+    let kept_of_dropped: Vec<IdxSize> = dropped.iter().map(|(_, kept)| *kept as IdxSize).collect();
+
+    let mut audit = df.take(&IdxCa::from_vec("rows", dropped_rows))?;
+    let kept_locations = df
+        .select([SOURCE, EXCEL_ROW])?
+        .take(&IdxCa::from_vec("rows", kept_of_dropped))?;
+    for (column, name) in [(SOURCE, KEPT_SOURCE), (EXCEL_ROW, KEPT_EXCEL_ROW)] {
+        let mut kept_location = kept_locations.column(column)?.clone();
+        kept_location.rename(name);
+        audit.with_column(kept_location)?;
+    }
+
+    if !duplicated.is_empty() {
+        println!(
+            "{} duplicated reference(s), {} row(s) left out",
+            duplicated.len(),
+            audit.height()
+        );
+    }
+    Ok(Deduplicated {
+        df: df.take(&IdxCa::from_vec("rows", kept_rows))?,
+        audit,
+    })
+}
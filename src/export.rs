@@ -0,0 +1,158 @@
+//! Draw the lineage table as a Graphviz or Mermaid graph.
+
+use crate::ingest::LOGICIEL_TYPE;
+use crate::lineage::{LINEAGE_ROOT, PARENT_REFERENCE, REFERENCE};
+use clap::ValueEnum;
+use polars::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Write as _;
+
+/// Graph description language of the export
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// Graphviz, render with `dot -Tsvg`
+    Dot,
+    /// Mermaid flowchart, rendered by GitLab, GitHub and most wikis
+    Mermaid,
+}
+
+/// Node of the graph, one per version
+struct Node {
+    id: String,
+    lines: [String; 3],
+    logiciel_type: Option<String>,
+}
+
+/// Versions of the lineages rooted at `base_references` (every lineage when empty), each version
+/// points to the versions based on it
+pub fn export_lineage(
+    df: &DataFrame,
+    base_references: &[String],
+    format: ExportFormat,
+) -> Result<String, Box<dyn Error>> {
+    let df = if base_references.is_empty() {
+        df.clone()
+    } else {
+        let mask: BooleanChunked = df
+            .column(LINEAGE_ROOT)?
+            .str()?
+            .into_iter()
+            .map(|root| root.is_some_and(|root| base_references.iter().any(|base| base == root)))
+            .collect();
+        let df = df.filter(&mask)?;
+        if df.height() == 0 {
+            return Err(format!(
+                "{} is not the root of any lineage",
+                base_references.join(", ")
+            )
+            .into());
+        }
+        df
+    };
+
+    let nodes = nodes(&df)?;
+    let references = df.column(REFERENCE)?.str()?;
+    let rows_by_reference: HashMap<&str, usize> = references
+        .into_iter()
+        .enumerate()
+        .filter_map(|(row, reference)| Some((reference?, row)))
+        .collect();
+    let edges: Vec<(usize, usize)> = df
+        .column(PARENT_REFERENCE)?
+        .str()?
+        .into_iter()
+        .enumerate()
+        .filter_map(|(row, parent)| Some((*rows_by_reference.get(parent?)?, row)))
+        .collect();
+
+    Ok(match format {
+        ExportFormat::Dot => to_dot(&nodes, &edges),
+        ExportFormat::Mermaid => to_mermaid(&nodes, &edges),
+    })
+}
+
+/// Reference, Creation Date and Author of each version
+fn nodes(df: &DataFrame) -> PolarsResult<Vec<Node>> {
+    let references = df.column(REFERENCE)?.str()?;
+    let creation_dates = df.column("Creation Date")?.cast(&DataType::Date)?;
+    let creation_dates = creation_dates.cast(&DataType::String)?;
+    let creation_dates = creation_dates.str()?;
+    let authors = df.column("Author")?.cast(&DataType::String)?;
+    let authors = authors.str()?;
+    let logiciel_types = df
+        .column(LOGICIEL_TYPE)
+        .ok()
+        .map(|logiciel_type| logiciel_type.str())
+        .transpose()?;
+
+    Ok((0..df.height())
+        .map(|row| Node {
+            id: format!("v{}", row),
+            lines: [
+                references.get(row).unwrap_or("<empty>").to_owned(),
+                creation_dates.get(row).unwrap_or("no date").to_owned(),
+                authors.get(row).unwrap_or("no author").to_owned(),
+            ],
+            logiciel_type: logiciel_types
+                .and_then(|logiciel_type| logiciel_type.get(row))
+                .map(str::to_owned),
+        })
+        .collect())
+}
+
+fn to_dot(nodes: &[Node], edges: &[(usize, usize)]) -> String {
+    let mut dot = String::from("digraph lineage {\n    rankdir=LR;\n");
+    dot.push_str("    node [fontname=\"Helvetica\", style=filled, fillcolor=\"#eeeeee\"];\n");
+    for node in nodes {
+        let style = match node.logiciel_type.as_deref() {
+            Some("code") => ", shape=box, fillcolor=\"#dbe9f6\"",
+            Some("etude") => ", shape=ellipse, fillcolor=\"#fde9d9\"",
+            _ => "",
+        };
+        let label: Vec<String> = node
+            .lines
+            .iter()
+            .map(|line| line.replace('\\', "\\\\").replace('"', "\\\""))
+            .collect();
+        let _ = writeln!(
+            dot,
+            "    {} [label=\"{}\"{}];",
+            node.id,
+            label.join("\\n"),
+            style
+        );
+    }
+    for (parent, child) in edges {
+        let _ = writeln!(dot, "    {} -> {};", nodes[*parent].id, nodes[*child].id);
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn to_mermaid(nodes: &[Node], edges: &[(usize, usize)]) -> String {
+    let mut mermaid = String::from("flowchart LR\n");
+    mermaid.push_str("    classDef code fill:#dbe9f6,stroke:#2b6cb0\n");
+    mermaid.push_str("    classDef etude fill:#fde9d9,stroke:#c05621\n");
+    for node in nodes {
+        let label: Vec<String> = node
+            .lines
+            .iter()
+            .map(|line| line.replace('"', "#quot;"))
+            .collect();
+        let label = label.join("<br/>");
+        let _ = match node.logiciel_type.as_deref() {
+            Some("etude") => writeln!(mermaid, "    {}([\"{}\"]):::etude", node.id, label),
+            Some("code") => writeln!(mermaid, "    {}[\"{}\"]:::code", node.id, label),
+            _ => writeln!(mermaid, "    {}[\"{}\"]", node.id, label),
+        };
+    }
+    for (parent, child) in edges {
+        let _ = writeln!(
+            mermaid,
+            "    {} --> {}",
+            nodes[*parent].id, nodes[*child].id
+        );
+    }
+    mermaid
+}
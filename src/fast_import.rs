@@ -0,0 +1,161 @@
+//! Write the versions as a `git fast-import` stream: commits, branches, annotated tags and their
+//! dates. The stream is written to a file to inspect and replay, or piped into `git fast-import`
+//! run in the repository.
+
+use crate::archive::for_each_project_file;
+use crate::git::{GitInfo, SKIPPED_REFS};
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+
+/// Commands of the stream, the commits it creates are referred to by their mark `:N`
+pub struct FastImport<W: Write> {
+    out: W,
+    marks: usize,
+}
+
+impl<W: Write> FastImport<W> {
+    /// The stream must end with `finish`: `git fast-import` updates no branch nor tag of a stream
+    /// cut short
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(b"feature done\n")?;
+        Ok(FastImport { out, marks: 0 })
+    }
+
+    /// Empty "First init" commit starting the root branch `branch`, its mark is returned
+    pub fn first_init(&mut self, git_info: &GitInfo, branch: &str) -> io::Result<String> {
+        let mark = self.commit_header(git_info, branch, "First init")?;
+        self.out.write_all(b"\n")?;
+        Ok(mark)
+    }
+
+    /// Commit the sources of `path_to_zip`, the tree of the parent when `None`, on `branch` on top
+    /// of `parent`, a mark or the id of a commit of the repository, and tag it with the reference
+    /// of the version. The mark of the commit is returned.
+    pub fn zip_to_commit(
+        &mut self,
+        git_info: &GitInfo,
+        branch: &str,
+        parent: &str,
+        path_to_zip: Option<&Path>,
+    ) -> Result<String, Box<dyn Error>> {
+        let mark = self.commit_header(git_info, branch, &git_info.commit_message)?;
+        writeln!(self.out, "from {}", parent)?;
+        if let Some(path_to_zip) = path_to_zip {
+            println!("Reading {} ", path_to_zip.display());
+            // The tree is the sources, whatever the parent holds
+            self.out.write_all(b"deleteall\n")?;
+            for_each_project_file(path_to_zip, |path, mode, content| {
+                writeln!(self.out, "M {:o} inline {}", mode, quote(&path.join("/")))?;
+                let mut bytes = Vec::new();
+                content.read_to_end(&mut bytes)?;
+                self.data(&bytes)?;
+                Ok(())
+            })?;
+        }
+        self.out.write_all(b"\n")?;
+        self.tag(git_info, &mark, "")?;
+        Ok(mark)
+    }
+
+    /// Annotated tag named after the reference of the version on `commit`, a mark or an id
+    pub fn tag(&mut self, git_info: &GitInfo, commit: &str, message: &str) -> io::Result<()> {
+        writeln!(self.out, "tag {}", git_info.branch_name)?;
+        writeln!(self.out, "from {}", commit)?;
+        writeln!(self.out, "tagger {}", identity(git_info)?)?;
+        self.data(message.as_bytes())
+    }
+
+    /// Mark the skipped version `reference` on `commit`, a mark or an id
+    pub fn mark_skipped(&mut self, reference: &str, commit: &str) -> io::Result<()> {
+        writeln!(self.out, "reset {}{}", SKIPPED_REFS, reference)?;
+        writeln!(self.out, "from {}", commit)?;
+        self.out.write_all(b"\n")
+    }
+
+    /// End the stream, the output is handed back
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(b"done\n")?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn commit_header(
+        &mut self,
+        git_info: &GitInfo,
+        branch: &str,
+        message: &str,
+    ) -> io::Result<String> {
+        self.marks += 1;
+        let mark = format!(":{}", self.marks);
+        let identity = identity(git_info)?;
+        writeln!(self.out, "commit refs/heads/{}", branch)?;
+        writeln!(self.out, "mark {}", mark)?;
+        writeln!(self.out, "author {}", identity)?;
+        writeln!(self.out, "committer {}", identity)?;
+        self.data(message.as_bytes())?;
+        Ok(mark)
+    }
+
+    /// Exact byte count, then the bytes
+    fn data(&mut self, content: &[u8]) -> io::Result<()> {
+        writeln!(self.out, "data {}", content.len())?;
+        self.out.write_all(content)?;
+        self.out.write_all(b"\n")
+    }
+}
+
+/// `git fast-import` run in the repository, reading the stream on its standard input
+pub fn spawn_git_fast_import(repo_path: &Path) -> io::Result<Child> {
+    Command::new("git")
+        .args(["fast-import", "--quiet"])
+        .current_dir(repo_path)
+        .stdin(Stdio::piped())
+        .spawn()
+}
+
+/// `Name <e-mail> seconds +hhmm` of the author of the version, an error when the name or the
+/// e-mail would break the line
+fn identity(git_info: &GitInfo) -> io::Result<String> {
+    let offset = git_info.utc_offset;
+    Ok(format!(
+        "{} <{}> {} {}{:02}{:02}",
+        identity_part(&git_info.author_name, "name", git_info)?,
+        identity_part(&git_info.author_email, "e-mail", git_info)?,
+        git_info.commit_time.unwrap_or(0),
+        if offset < 0 { '-' } else { '+' },
+        offset.abs() / 60,
+        offset.abs() % 60
+    ))
+}
+
+/// Author name or e-mail trimmed as libgit2 trims it, so that every backend signs alike
+fn identity_part<'a>(value: &'a str, part: &str, git_info: &GitInfo) -> io::Result<&'a str> {
+    let is_crud = |c: char| c <= ' ' || ".,:;<>\"\\'".contains(c);
+    let trimmed = value.trim_matches(is_crud);
+    if trimmed.is_empty() || value.contains(['<', '>']) || trimmed.contains(['\n', '\0']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Author {} `{}` of {} is empty or holds `<`, `>` or a line break",
+                part,
+                value.escape_debug(),
+                git_info.branch_name
+            ),
+        ));
+    }
+    Ok(trimmed)
+}
+
+/// A path is quoted, C-style, when it starts with a quote or holds a line feed
+fn quote(path: &str) -> String {
+    if !path.starts_with('"') && !path.contains('\n') {
+        return path.to_owned();
+    }
+    let escaped = path
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
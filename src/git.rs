@@ -0,0 +1,318 @@
+//! Write the versions into a git repository: branches, commits and tags.
+
+use std::path::Path;
+
+/// Whether the repository had to be created
+pub enum GitState {
+    AlreadyInit,
+    FirstInit,
+}
+
+/// Commit details of one version
+pub struct GitInfo {
+    /// Unix timestamp of the commit, in seconds
+    pub commit_time: Option<i64>,
+    /// Offset of the local time of the commit from UTC, in minutes
+    pub utc_offset: i32,
+    pub commit_message: String,
+    pub author_name: String,
+    pub author_email: String,
+    /// Reference of the version, also the name of its branch and of its tag
+    pub branch_name: String,
+}
+
+impl GitInfo {
+    pub fn new(
+        commit_time: Option<i64>,
+        utc_offset: i32,
+        commit_message: String,
+        author_name: String,
+        author_email: String,
+        branch_name: String,
+    ) -> Self {
+        GitInfo {
+            commit_time,
+            utc_offset,
+            commit_message,
+            author_name,
+            author_email,
+            branch_name,
+        }
+    }
+
+    /// Author and committer of the commits of the version, at its local time
+    pub(crate) fn signature(&self) -> Result<git2::Signature<'static>, git2::Error> {
+        git2::Signature::new(
+            &self.author_name,
+            &self.author_email,
+            &git2::Time::new(self.commit_time.unwrap_or(0), self.utc_offset),
+        )
+    }
+}
+
+/// Create the folder and an empty repository in it, bare or not, unless it already holds one
+pub fn create_folder_and_init_git_repo(
+    repository_path: &Path,
+    bare: bool,
+) -> Result<GitState, git2::Error> {
+    if is_git_repo(repository_path) {
+        println!("Git folder already exists in {}", repository_path.display());
+        return Ok(GitState::AlreadyInit);
+    }
+
+    std::fs::create_dir_all(repository_path).expect("Could not create the directory");
+    git2::Repository::init_opts(
+        repository_path,
+        git2::RepositoryInitOptions::new().bare(bare),
+    )?;
+    println!(
+        "Git repository is initialized in {}",
+        repository_path.display()
+    );
+    Ok(GitState::FirstInit)
+}
+
+/// Whether `path` is a repository, bare or with its `.git` folder
+pub fn is_git_repo(path: &Path) -> bool {
+    git2::Repository::open(path).is_ok()
+}
+
+/// Stage every file of the working tree, the files deleted from it included
+pub fn git_add_all(repo_path: &Path) -> Result<(), git2::Error> {
+    let mut opts = git2::StatusOptions::new();
+    opts.include_untracked(true);
+    let repo = git2::Repository::open(repo_path)?;
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    index.update_all(["*"].iter(), None)?;
+    index.write()?;
+    Ok(())
+}
+
+/// Commit the index on top of HEAD and tag the commit with the reference of the version, the id
+/// of the commit is returned
+pub fn git_commit(repo_path: &Path, git_info: &GitInfo) -> Result<String, git2::Error> {
+    let repo = git2::Repository::open(repo_path)?;
+    let signature = git_info.signature()?;
+    let tree_id = repo.index()?.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+    let head_commit = repo.head()?.peel_to_commit()?;
+    let commit_id = repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        &git_info.commit_message,
+        &tree,
+        &[&head_commit],
+    )?;
+    let commit = repo.find_object(commit_id, Some(git2::ObjectType::Commit))?;
+    repo.tag(&git_info.branch_name, &commit, &signature, "", false)?;
+    Ok(commit_id.to_string())
+}
+
+/// Commit `tree`, the tree of `parent` when `None`, on `branch_name` without a checkout: the
+/// branch is created when it doesn't exist. The commit is tagged with the reference of the
+/// version and its id returned.
+pub fn git_commit_tree(
+    repo_path: &Path,
+    git_info: &GitInfo,
+    branch_name: &str,
+    parent: &str,
+    tree: Option<git2::Oid>,
+) -> Result<String, git2::Error> {
+    let repo = git2::Repository::open(repo_path)?;
+    let parent = repo.find_commit(git2::Oid::from_str(parent)?)?;
+    let tree = match tree {
+        Some(tree_id) => repo.find_tree(tree_id)?,
+        None => parent.tree()?,
+    };
+    let signature = git_info.signature()?;
+    let commit_id = repo.commit(
+        Some(&format!("refs/heads/{}", branch_name)),
+        &signature,
+        &signature,
+        &git_info.commit_message,
+        &tree,
+        &[&parent],
+    )?;
+    let commit = repo.find_object(commit_id, Some(git2::ObjectType::Commit))?;
+    repo.tag(&git_info.branch_name, &commit, &signature, "", false)?;
+    Ok(commit_id.to_string())
+}
+
+/// Tag an existing commit with the reference of the version, the message of the version goes
+/// into the tag
+pub fn git_tag_commit(
+    repo_path: &Path,
+    git_info: &GitInfo,
+    commit_hash: &str,
+) -> Result<(), git2::Error> {
+    let repo = git2::Repository::open(repo_path)?;
+    let commit = repo.find_object(
+        git2::Oid::from_str(commit_hash)?,
+        Some(git2::ObjectType::Commit),
+    )?;
+    repo.tag(
+        &git_info.branch_name,
+        &commit,
+        &git_info.signature()?,
+        &git_info.commit_message,
+        false,
+    )?;
+    Ok(())
+}
+
+/// Namespace of the references marking the versions left out by `--no-zip skip`: no tag, the
+/// reference points to the commit the versions based on it are based on
+pub const SKIPPED_REFS: &str = "refs/skipped/";
+
+/// Mark the version `reference` as skipped on `commit_hash`, so that `verify` and incremental
+/// pushes know it
+pub fn git_mark_skipped(
+    repo_path: &Path,
+    reference: &str,
+    commit_hash: &str,
+) -> Result<(), git2::Error> {
+    let repo = git2::Repository::open(repo_path)?;
+    repo.reference(
+        &format!("{}{}", SKIPPED_REFS, reference),
+        git2::Oid::from_str(commit_hash)?,
+        true,
+        "no zip of its own, skipped",
+    )?;
+    Ok(())
+}
+
+/// Create `branch_name` at `commit_hash` and check it out
+pub fn git_create_and_switch_to_new_branch_from_commit(
+    branch_name: &str,
+    repo_path: &Path,
+    commit_hash: &str,
+) -> Result<(), git2::Error> {
+    let repo = git2::Repository::open(repo_path)?;
+    let commit_object = repo.revparse_single(commit_hash)?;
+    let commit = commit_object.peel_to_commit()?;
+    let reference_name = format!("refs/heads/{}", branch_name);
+    repo.branch(branch_name, &commit, false)?;
+    let obj = repo.revparse_single(&reference_name).unwrap();
+    repo.checkout_tree(&obj, None)?;
+    repo.set_head(&reference_name)?;
+    println!(
+        "Branch {} is created. Main branch is switched to that branch.",
+        branch_name
+    );
+    Ok(())
+}
+
+/// Start a new root branch with an empty "First init" commit and check it out, the versions of
+/// the lineage are committed on top of it
+pub fn git_init_and_switch_to_first_branch(
+    repo_path: &Path,
+    git_info: &GitInfo,
+) -> Result<(), git2::Error> {
+    let repo = git2::Repository::open(repo_path)?;
+    let reference_name = format!("refs/heads/{}", &git_info.branch_name);
+    // Another base reference has already been imported: start an unrelated root on an empty index
+    let previous_root = repo.head().is_ok();
+    let mut index = repo.index()?;
+    if previous_root {
+        repo.set_head(&reference_name)?;
+        index.clear()?;
+        index.write()?;
+    }
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+    let signature = git_info.signature()?;
+
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "First init",
+        &tree,
+        &[],
+    )?;
+    if !previous_root {
+        repo.branch(
+            &git_info.branch_name,
+            &repo.head()?.peel_to_commit()?,
+            false,
+        )?;
+    }
+    let obj = repo.revparse_single(&reference_name).unwrap();
+    repo.checkout_tree(&obj, None)?;
+    repo.set_head(&reference_name)?;
+    println!(
+        "Branch {} is created. Main branch is switched to that branch.",
+        &git_info.branch_name
+    );
+    Ok(())
+}
+
+/// Commit tagged with `reference` by a previous import, or marked with it when the version was
+/// skipped
+pub fn find_tagged_commit(
+    repo_path: &Path,
+    reference: &str,
+) -> Result<Option<String>, git2::Error> {
+    let repo = git2::Repository::open(repo_path)?;
+    for name in [
+        format!("refs/tags/{}", reference),
+        format!("{}{}", SKIPPED_REFS, reference),
+    ] {
+        match repo.find_reference(&name) {
+            Ok(found) => return Ok(Some(found.peel_to_commit()?.id().to_string())),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+/// First parent of a commit, `None` for a root commit
+pub fn first_parent(repo_path: &Path, commit_hash: &str) -> Result<Option<String>, git2::Error> {
+    let repo = git2::Repository::open(repo_path)?;
+    let commit = repo.find_commit(git2::Oid::from_str(commit_hash)?)?;
+    Ok(commit.parent_ids().next().map(|parent| parent.to_string()))
+}
+
+/// Head commit of every local branch
+pub fn branch_heads(repo_path: &Path) -> Result<Vec<(String, String)>, git2::Error> {
+    let repo = git2::Repository::open(repo_path)?;
+    let mut heads: Vec<(String, String)> = Vec::new();
+    for branch in repo.branches(Some(git2::BranchType::Local))? {
+        let (branch, _) = branch?;
+        if let (Some(name), Some(commit_id)) = (branch.name()?, branch.get().target()) {
+            heads.push((name.to_owned(), commit_id.to_string()));
+        }
+    }
+    Ok(heads)
+}
+
+/// Check out an existing branch, the index and the working tree are reset to its head
+pub fn git_switch_to_branch(repo_path: &Path, branch_name: &str) -> Result<(), git2::Error> {
+    let repo = git2::Repository::open(repo_path)?;
+    let reference_name = format!("refs/heads/{}", branch_name);
+    if repo
+        .head()
+        .ok()
+        .and_then(|head| head.name().map(str::to_owned))
+        == Some(reference_name.clone())
+    {
+        return Ok(());
+    }
+    repo.set_head(&reference_name)?;
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))?;
+    println!("Switched to branch {}", branch_name);
+    Ok(())
+}
+
+/// Point HEAD at `branch`, the working tree of a non-bare repository is checked out
+pub fn git_set_head_to_branch(repo_path: &Path, branch_name: &str) -> Result<(), git2::Error> {
+    let repo = git2::Repository::open(repo_path)?;
+    repo.set_head(&format!("refs/heads/{}", branch_name))?;
+    if !repo.is_bare() {
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))?;
+    }
+    Ok(())
+}
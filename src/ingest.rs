@@ -0,0 +1,633 @@
+//! Read the Excel exports into typed DataFrames and build their `Reference` column.
+
+use crate::date_mode;
+use calamine::{open_workbook_auto, Range, Reader, Sheets};
+use csv::Writer;
+use polars::prelude::*;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Workbook formats calamine can read
+pub const WORKBOOK_EXTENSIONS: [&str; 5] = ["xls", "xlsx", "xlsm", "xlsb", "ods"];
+
+/// Columns every export must contain, whatever its `LogicielType`
+const COMMON_HEADERS: [&str; 5] = ["Target", "Based On", "Creation Date", "Author", "Comments"];
+
+/// Columns kept as the text shown in Excel, whatever the type of their cells
+const TEXT_COLUMNS: [&str; 3] = ["Target", "Based On", "Study Number"];
+
+/// Columns parsed into `Datetime`, whether Excel stores a serial number or a text
+pub const DATE_COLUMNS: [&str; 3] = ["Creation Date", "Archive Date", "Expedition Date"];
+
+/// Workbook each row comes from, and its row number as shown by Excel
+pub const SOURCE: &str = "Source";
+pub const EXCEL_ROW: &str = "Excel Row";
+/// `LogicielType` of the workbook each row comes from
+pub const LOGICIEL_TYPE: &str = "Logiciel Type";
+
+/// Textual dates found in the exports, day first as in the French exports
+const DATETIME_FORMATS: [&str; 5] = [
+    "%d/%m/%Y %H:%M:%S",
+    "%d/%m/%Y %H:%M",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+];
+const DATE_FORMATS: [&str; 4] = ["%d/%m/%Y", "%d.%m.%Y", "%d-%m-%Y", "%Y-%m-%d"];
+
+/// Epoch of the serial dates of a workbook, the fraction of a serial date is the time of day
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum DateSystem {
+    /// Day 1 is 1900-01-01, and day 60 the 29 February 1900 that Excel counts but never was
+    #[serde(rename = "1900")]
+    Excel1900,
+    /// Day 0 is 1904-01-01, the default of Excel for Mac before 2011
+    #[serde(rename = "1904")]
+    Excel1904,
+}
+
+impl DateSystem {
+    /// Date system declared by the workbook, an error when the workbook cannot be read for it
+    pub fn detect(excel_path: &Path) -> Result<DateSystem, Box<dyn Error>> {
+        let extension = excel_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        let declared_1904 = match extension.as_deref() {
+            Some("xlsx" | "xlsm") => zip_entry(excel_path, "xl/workbook.xml")
+                .map(|xml| date_mode::xlsx_declares_1904(&xml)),
+            Some("xlsb") => zip_entry(excel_path, "xl/workbook.bin")
+                .and_then(|records| date_mode::xlsb_declares_1904(&records)),
+            Some("xls") => std::fs::read(excel_path)
+                .ok()
+                .and_then(|bytes| date_mode::xls_declares_1904(&bytes)),
+            Some("ods") => {
+                zip_entry(excel_path, "content.xml").map(|xml| date_mode::ods_declares_1904(&xml))
+            }
+            _ => None,
+        };
+        match declared_1904 {
+            Some(true) => Ok(DateSystem::Excel1904),
+            Some(false) => Ok(DateSystem::Excel1900),
+            None => Err(format!(
+                "Cannot read the date system of workbook {}, set its date_system to 1900 or 1904",
+                excel_path.display()
+            )
+            .into()),
+        }
+    }
+
+    /// Milliseconds since the Unix epoch of a serial date, to the second
+    pub fn serial_to_millis(&self, serial: f64) -> i64 {
+        // Days since 1899-12-30, the epoch that makes every 1900 serial from 1 March 1900 exact
+        let days = match self {
+            DateSystem::Excel1904 => serial + 1462.0,
+            // Before the 29 February 1900 that never was, day 1 is 1900-01-01: the 29 February
+            // itself is read as the 28
+            DateSystem::Excel1900 if serial < 60.0 => serial + 1.0,
+            DateSystem::Excel1900 => serial,
+        };
+        const UNIX_EPOCH_DAYS: f64 = 25569.0;
+        ((days - UNIX_EPOCH_DAYS) * 86_400.0).round() as i64 * 1000
+    }
+}
+
+impl FromStr for DateSystem {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "1900" => Ok(DateSystem::Excel1900),
+            "1904" => Ok(DateSystem::Excel1904),
+            _ => Err(format!(
+                "unknown date system `{}`, expected 1900 or 1904",
+                value
+            )),
+        }
+    }
+}
+
+/// Content of one file of a zipped workbook
+fn zip_entry(excel_path: &Path, name: &str) -> Option<Vec<u8>> {
+    let mut archive = ::zip::ZipArchive::new(File::open(excel_path).ok()?).ok()?;
+    let mut content = Vec::new();
+    archive.by_name(name).ok()?.read_to_end(&mut content).ok()?;
+    Some(content)
+}
+
+/// Kind of export, each one builds its `Reference` from different columns
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogicielType {
+    Code,
+    Etude,
+}
+
+impl FromStr for LogicielType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "code" => Ok(LogicielType::Code),
+            "etude" => Ok(LogicielType::Etude),
+            _ => Err(format!(
+                "unknown LogicielType `{}`, expected code or etude",
+                value
+            )),
+        }
+    }
+}
+
+impl LogicielType {
+    /// Value of the `Logiciel Type` column, as written in the migration file
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogicielType::Code => "code",
+            LogicielType::Etude => "etude",
+        }
+    }
+
+    /// Columns needed to build the `Reference` column
+    pub fn reference_headers(&self) -> [&'static str; 2] {
+        match self {
+            LogicielType::Code => ["Software P/N", "Version"],
+            LogicielType::Etude => ["Préf", "Number"],
+        }
+    }
+
+    fn required_headers(&self) -> Vec<&'static str> {
+        let mut headers = COMMON_HEADERS.to_vec();
+        headers.extend(self.reference_headers());
+        headers
+    }
+}
+
+pub fn is_supported_workbook(excel_path: &Path) -> bool {
+    excel_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| WORKBOOK_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Open a workbook whatever its format, the format is detected from the extension
+fn open_excel(excel_path: &Path) -> Result<Sheets<BufReader<File>>, Box<dyn Error>> {
+    if !is_supported_workbook(excel_path) {
+        return Err(format!(
+            "Unsupported workbook {}, expected one of .{}",
+            excel_path.display(),
+            WORKBOOK_EXTENSIONS.join(", .")
+        )
+        .into());
+    }
+    open_workbook_auto(excel_path)
+        .map_err(|e| format!("Cannot open workbook {}: {}", excel_path.display(), e).into())
+}
+
+/// Position of the header row and number of columns to keep
+struct HeaderLayout {
+    row: usize,
+    width: usize,
+}
+
+/// Row number shown by Excel for a row of `range`, which doesn't always start at A1
+fn excel_row(range: &Range<calamine::DataType>, range_row: usize) -> u32 {
+    range.start().map_or(0, |(first_row, _)| first_row) + range_row as u32 + 1
+}
+
+/// Look for the first row holding every required header, the table then spans up to the last
+/// non-empty header of that row
+fn find_header_row(
+    range: &Range<calamine::DataType>,
+    required_headers: &[&str],
+) -> Result<HeaderLayout, String> {
+    // Closest candidate, to tell the user which headers are missing
+    let mut best: Option<(usize, Vec<&str>)> = None;
+    for (row_index, row) in range.rows().enumerate() {
+        let cells: Vec<String> = row.iter().map(|cell| cell.to_string()).collect();
+        let missing: Vec<&str> = required_headers
+            .iter()
+            .filter(|header| !cells.iter().any(|cell| cell.trim() == **header))
+            .copied()
+            .collect();
+        if missing.is_empty() {
+            let width = cells
+                .iter()
+                .rposition(|cell| !cell.trim().is_empty())
+                .map_or(0, |index| index + 1);
+            return Ok(HeaderLayout {
+                row: row_index,
+                width,
+            });
+        }
+        if best
+            .as_ref()
+            .is_none_or(|(_, best_missing)| missing.len() < best_missing.len())
+        {
+            best = Some((row_index, missing));
+        }
+    }
+
+    match best {
+        Some((row_index, missing)) if missing.len() < required_headers.len() => Err(format!(
+            "no header row found, the closest one (row {}) misses the columns: {}",
+            excel_row(range, row_index),
+            missing.join(", ")
+        )),
+        _ => Err(format!(
+            "no header row found, expected the columns: {}",
+            required_headers.join(", ")
+        )),
+    }
+}
+
+/// Table of the first sheet: its headers and the rows below them
+struct SheetTable {
+    sheet_name: String,
+    layout: HeaderLayout,
+    range: Range<calamine::DataType>,
+}
+
+impl SheetTable {
+    fn read(excel_path: &Path, logiciel_type: LogicielType) -> Result<Self, Box<dyn Error>> {
+        let mut excel = open_excel(excel_path)?;
+        let sheet_name = excel
+            .sheet_names()
+            .first()
+            .cloned()
+            .ok_or_else(|| format!("Workbook {} has no sheet", excel_path.display()))?;
+        let range = excel.worksheet_range(&sheet_name)?;
+        let layout = find_header_row(&range, &logiciel_type.required_headers()).map_err(|e| {
+            format!(
+                "Workbook {} (sheet {}): {}",
+                excel_path.display(),
+                sheet_name,
+                e
+            )
+        })?;
+        Ok(SheetTable {
+            sheet_name,
+            layout,
+            range,
+        })
+    }
+
+    /// Headers of the table, unnamed columns inside the table get a placeholder name
+    fn headers(&self) -> Vec<String> {
+        self.range
+            .rows()
+            .nth(self.layout.row)
+            .unwrap_or(&[])
+            .iter()
+            .take(self.layout.width)
+            .enumerate()
+            .map(|(index, cell)| match cell.to_string().trim() {
+                "" => format!("column_{}", index + 1),
+                header => header.to_owned(),
+            })
+            .collect()
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &[calamine::DataType]> {
+        self.range
+            .rows()
+            .skip(self.layout.row + 1)
+            .map(|row| &row[..self.layout.width.min(row.len())])
+    }
+}
+
+/// Debug output: write the table of the first sheet as it is read from the workbook
+pub fn convert_excel_to_csv(
+    excel_path: &Path,
+    csv_path: &Path,
+    logiciel_type: LogicielType,
+) -> Result<(), Box<dyn Error>> {
+    let table = SheetTable::read(excel_path, logiciel_type)?;
+
+    // Create a CSV writer
+    let csv_file = File::create(csv_path)?;
+    let mut csv_writer = Writer::from_writer(csv_file);
+    csv_writer.write_record(table.headers())?;
+    for row in table.rows() {
+        let csv_row: Vec<String> = row.iter().map(|cell| format!("{}", cell)).collect();
+        csv_writer.write_record(&csv_row)?;
+    }
+
+    println!("CSV Conversion successful: {}", csv_path.display());
+    Ok(())
+}
+
+/// Read the table of the first sheet into a DataFrame, each column gets the dtype of its cells
+pub fn read_excel_to_dataframe(
+    excel_path: &Path,
+    logiciel_type: LogicielType,
+    date_system: DateSystem,
+) -> Result<DataFrame, Box<dyn Error>> {
+    let table = SheetTable::read(excel_path, logiciel_type)?;
+    let rows: Vec<&[calamine::DataType]> = table.rows().collect();
+
+    let columns = table
+        .headers()
+        .iter()
+        .enumerate()
+        .map(|(index, header)| {
+            let cells: Vec<&calamine::DataType> = rows
+                .iter()
+                .map(|row| row.get(index).unwrap_or(&calamine::DataType::Empty))
+                .collect();
+            if TEXT_COLUMNS.contains(&header.as_str()) {
+                text_series(header, &cells)
+            } else if DATE_COLUMNS.contains(&header.as_str()) {
+                Ok(date_series(header, &cells, excel_path, date_system))
+            } else {
+                cells_to_series(header, &cells)
+            }
+        })
+        .collect::<PolarsResult<Vec<Series>>>()?;
+
+    let mut df = DataFrame::new(columns)?;
+    let source = excel_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let excel_rows: Vec<u32> = (0..rows.len())
+        .map(|index| excel_row(&table.range, table.layout.row + 1 + index))
+        .collect();
+    df.with_column(Series::new(SOURCE, vec![source; rows.len()]))?;
+    df.with_column(Series::new(EXCEL_ROW, excel_rows))?;
+    df.with_column(Series::new(
+        LOGICIEL_TYPE,
+        vec![logiciel_type.as_str(); rows.len()],
+    ))?;
+    println!(
+        "Workbook {} (sheet {}) read: header on row {}, {} columns, {} rows",
+        excel_path.display(),
+        table.sheet_name,
+        excel_row(&table.range, table.layout.row),
+        df.width() - 3,
+        df.height()
+    );
+    Ok(df)
+}
+
+/// Dtype shared by the non-empty cells of a column
+#[derive(Clone, Copy, PartialEq)]
+enum CellKind {
+    Null,
+    Int,
+    Float,
+    Bool,
+    Datetime,
+    String,
+}
+
+fn cell_kind(cell: &calamine::DataType) -> CellKind {
+    match cell {
+        calamine::DataType::Empty | calamine::DataType::Error(_) => CellKind::Null,
+        calamine::DataType::Int(_) => CellKind::Int,
+        // .xls files store every number as a float
+        calamine::DataType::Float(value) if value.fract() == 0.0 && value.abs() < 1e15 => {
+            CellKind::Int
+        }
+        calamine::DataType::Float(_) | calamine::DataType::Duration(_) => CellKind::Float,
+        calamine::DataType::Bool(_) => CellKind::Bool,
+        calamine::DataType::DateTime(_) => CellKind::Datetime,
+        calamine::DataType::String(_)
+        | calamine::DataType::DateTimeIso(_)
+        | calamine::DataType::DurationIso(_) => CellKind::String,
+    }
+}
+
+fn column_kind(cells: &[&calamine::DataType]) -> CellKind {
+    cells
+        .iter()
+        .map(|cell| cell_kind(cell))
+        .fold(CellKind::Null, |kind, cell| match (kind, cell) {
+            (kind, CellKind::Null) => kind,
+            (CellKind::Null, cell) => cell,
+            (kind, cell) if kind == cell => kind,
+            (CellKind::Int, CellKind::Float) | (CellKind::Float, CellKind::Int) => CellKind::Float,
+            _ => CellKind::String,
+        })
+}
+
+fn text_series(name: &str, cells: &[&calamine::DataType]) -> PolarsResult<Series> {
+    let values: Vec<Option<String>> = cells.iter().map(|cell| cell_text(cell)).collect();
+    Ok(Series::new(name, values))
+}
+
+fn cell_text(cell: &calamine::DataType) -> Option<String> {
+    match cell {
+        calamine::DataType::Empty | calamine::DataType::Error(_) => None,
+        cell => Some(cell.to_string()),
+    }
+}
+
+fn cells_to_series(name: &str, cells: &[&calamine::DataType]) -> PolarsResult<Series> {
+    let series = match column_kind(cells) {
+        CellKind::Null => Series::full_null(name, cells.len(), &DataType::Null),
+        CellKind::Int => {
+            let values: Vec<Option<i64>> = cells
+                .iter()
+                .map(|cell| match cell {
+                    calamine::DataType::Int(value) => Some(*value),
+                    calamine::DataType::Float(value) => Some(*value as i64),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+        CellKind::Float => {
+            let values: Vec<Option<f64>> = cells
+                .iter()
+                .map(|cell| match cell {
+                    calamine::DataType::Int(value) => Some(*value as f64),
+                    calamine::DataType::Float(value) | calamine::DataType::Duration(value) => {
+                        Some(*value)
+                    }
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+        CellKind::Bool => {
+            let values: Vec<Option<bool>> = cells
+                .iter()
+                .map(|cell| match cell {
+                    calamine::DataType::Bool(value) => Some(*value),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+        CellKind::Datetime => {
+            let values: Vec<Option<i64>> = cells
+                .iter()
+                .map(|cell| match cell {
+                    calamine::DataType::DateTime(serial) => {
+                        Some(DateSystem::Excel1900.serial_to_millis(*serial))
+                    }
+                    _ => None,
+                })
+                .collect();
+            datetime_series(name, values)
+        }
+        CellKind::String => text_series(name, cells)?,
+    };
+    Ok(series)
+}
+
+fn datetime_series(name: &str, millis: Vec<Option<i64>>) -> Series {
+    Int64Chunked::from_iter_options(name, millis.into_iter())
+        .into_datetime(TimeUnit::Milliseconds, None)
+        .into_series()
+}
+
+/// Date column: serial numbers and textual dates are both accepted, the values that can't be
+/// parsed are reported and left empty
+fn date_series(
+    name: &str,
+    cells: &[&calamine::DataType],
+    excel_path: &Path,
+    date_system: DateSystem,
+) -> Series {
+    let mut unparsable: Vec<String> = Vec::new();
+    let millis: Vec<Option<i64>> = cells
+        .iter()
+        .map(|cell| {
+            cell_millis(cell, date_system).unwrap_or_else(|| {
+                unparsable.push(cell.to_string());
+                None
+            })
+        })
+        .collect();
+    if !unparsable.is_empty() {
+        eprintln!(
+            "Warning: {} value(s) of column {} in {} are not dates and are left empty: {}",
+            unparsable.len(),
+            name,
+            excel_path.display(),
+            unparsable.join(", ")
+        );
+    }
+    datetime_series(name, millis)
+}
+
+/// `None` when the cell holds something that isn't a date, `Some(None)` when it is empty
+fn cell_millis(cell: &calamine::DataType, date_system: DateSystem) -> Option<Option<i64>> {
+    match cell {
+        calamine::DataType::Empty => Some(None),
+        // calamine already moves the date cells of a 1904 workbook to the 1900 system
+        calamine::DataType::DateTime(serial) => {
+            Some(Some(DateSystem::Excel1900.serial_to_millis(*serial)))
+        }
+        calamine::DataType::Float(serial) => Some(Some(date_system.serial_to_millis(*serial))),
+        calamine::DataType::Int(serial) => Some(Some(date_system.serial_to_millis(*serial as f64))),
+        calamine::DataType::String(text) | calamine::DataType::DateTimeIso(text) => {
+            let text = text.trim();
+            if text.is_empty() {
+                Some(None)
+            } else {
+                parse_date_text(text).map(Some)
+            }
+        }
+        _ => None,
+    }
+}
+
+fn parse_date_text(text: &str) -> Option<i64> {
+    let datetime = DATETIME_FORMATS
+        .iter()
+        .find_map(|format| chrono::NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            DATE_FORMATS
+                .iter()
+                .find_map(|format| chrono::NaiveDate::parse_from_str(text, format).ok())
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })?;
+    Some(datetime.and_utc().timestamp_millis())
+}
+
+/// Stack frames whose columns differ in order, presence or dtype: missing columns are filled
+/// with nulls and a column takes the dtype of its non-null occurrences (String when they differ
+/// or when the column is empty everywhere)
+pub fn concat_aligned(frames: &[DataFrame]) -> PolarsResult<DataFrame> {
+    let mut schema: Vec<(String, DataType)> = Vec::new();
+    for frame in frames {
+        for series in frame.get_columns() {
+            let dtype = series.dtype().clone();
+            match schema.iter_mut().find(|(name, _)| name == series.name()) {
+                None => schema.push((series.name().to_owned(), dtype)),
+                Some((_, current)) if *current == DataType::Null => *current = dtype,
+                Some((_, current)) if dtype == DataType::Null || *current == dtype => {}
+                Some((_, current)) if current.is_numeric() && dtype.is_numeric() => {
+                    *current = DataType::Float64
+                }
+                Some((_, current)) => *current = DataType::String,
+            }
+        }
+    }
+
+    for (_, dtype) in schema.iter_mut() {
+        if *dtype == DataType::Null {
+            *dtype = DataType::String;
+        }
+    }
+
+    let mut stacked: Option<DataFrame> = None;
+    for frame in frames {
+        let columns = schema
+            .iter()
+            .map(|(name, dtype)| match frame.column(name) {
+                Ok(series) => series.cast(dtype),
+                Err(_) => Ok(Series::full_null(name, frame.height(), dtype)),
+            })
+            .collect::<PolarsResult<Vec<Series>>>()?;
+        let aligned = DataFrame::new(columns)?;
+        stacked = Some(match stacked {
+            Some(df) => df.vstack(&aligned)?,
+            None => aligned,
+        });
+    }
+    Ok(stacked.unwrap_or_default())
+}
+
+/// Columns derived from the columns of an export
+pub trait AddColumn {
+    /// `Reference` of each version: `<Software P/N>-<Version>` for code, `Préf * 1000000 + Number`
+    /// for etude
+    fn add_reference_column(&mut self, lf_name: LogicielType);
+}
+
+impl AddColumn for LazyFrame {
+    fn add_reference_column(&mut self, lf_name: LogicielType) {
+        match lf_name {
+            LogicielType::Etude => {
+                *self = self
+                    .clone()
+                    .with_columns([(col("Préf").cast(DataType::Int64) * lit(1000000)
+                        + col("Number").cast(DataType::Int64))
+                    .cast(DataType::String)
+                    .alias("Reference")]);
+            }
+
+            LogicielType::Code => {
+                *self = self
+                    .clone()
+                    .with_columns([when(col("Version").is_not_null())
+                        .then(
+                            col("Software P/N").cast(DataType::String)
+                                + lit("-")
+                                + col("Version").cast(DataType::String),
+                        )
+                        .otherwise(col("Software P/N").cast(DataType::String))
+                        .cast(DataType::String)
+                        .alias("Reference")]);
+            }
+        }
+    }
+}
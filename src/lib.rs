@@ -0,0 +1,51 @@
+//! Rebuild the git history of legacy software from its Excel exports and source zips.
+//!
+//! The pipeline runs in two steps, each one behind a binary:
+//!
+//! - [`prepare::prepare`] reads the workbooks ([`ingest`]), drops the duplicated references
+//!   ([`duplicates`]), links the versions ([`lineage`]) and writes the tables and the
+//!   data-quality report ([`quality`]) as .parquet files. [`export`] draws the lineage table.
+//! - [`push::push`] reads the lineage table back and commits the sources of every version
+//!   ([`archive`]) into a git repository ([`git`], straight into its objects with [`odb`] or
+//!   through a [`fast_import`] stream), one branch per path of the lineage, under the identities
+//!   of [`authors`] and with the messages of [`message`]. It executes the [`plan`] of the
+//!   import, which can also be printed without touching the repository.
+//!
+//! Both steps are configured by [`config::PrepareSettings`] and [`config::PushSettings`], built
+//! from a migration file and from overrides such as command line flags.
+
+pub mod archive;
+pub mod authors;
+pub mod config;
+pub mod date_mode;
+pub mod duplicates;
+pub mod export;
+pub mod fast_import;
+pub mod git;
+pub mod ingest;
+pub mod lineage;
+pub mod message;
+pub mod odb;
+pub mod plan;
+pub mod prepare;
+pub mod push;
+pub mod quality;
+
+use std::path::{Path, PathBuf};
+
+/// a wrapper for windows to get path of a file or a directory
+pub fn get_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    if cfg!(target_os = "windows") {
+        let path_buf = path.as_ref().to_path_buf();
+        // Convert path to Windows style if the OS is Windows
+        if let Some(p) = path_buf.to_str() {
+            if p.contains('/') {
+                return PathBuf::from(p.replace('/', "\\"));
+            }
+        }
+        return path_buf;
+    }
+
+    // For non-Windows systems, return the input path as it is
+    path.as_ref().to_path_buf()
+}
@@ -0,0 +1,484 @@
+//! Link the versions through their `Based On` column, write and read back the lineage table.
+
+use clap::ValueEnum;
+use polars::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+/// What to do when the Based On column loops back on itself
+#[derive(Clone, Copy, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CyclePolicy {
+    /// Stop and report the cycles
+    #[default]
+    Abort,
+    /// Drop the Based On link of the oldest version of each cycle, which becomes a root
+    Break,
+}
+
+/// Positions of the `Reference` and `Based On` values, built once so that linking the versions
+/// doesn't rescan the table at every step
+struct LineageIndex<'a> {
+    references: Vec<Option<&'a str>>,
+    based_on: Vec<Option<&'a str>>,
+    /// Every row holding a given reference, in table order. A row without a reference is no
+    /// version's parent.
+    rows_by_reference: HashMap<&'a str, Vec<usize>>,
+    /// Row of the version each row is based on, cycles already resolved
+    parents: Vec<Option<usize>>,
+    /// First (most recent) row based on a given reference, the roots aside
+    first_child: HashMap<&'a str, usize>,
+}
+
+impl<'a> LineageIndex<'a> {
+    fn new(df: &'a DataFrame, on_cycle: CyclePolicy) -> Result<Self, Box<dyn Error>> {
+        let references: Vec<Option<&str>> = df.column("Reference")?.str()?.into_iter().collect();
+        let based_on: Vec<Option<&str>> = df.column("Based On")?.str()?.into_iter().collect();
+
+        let mut rows_by_reference: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (row, reference) in references.iter().enumerate() {
+            if let Some(reference) = reference {
+                rows_by_reference.entry(reference).or_default().push(row);
+            }
+        }
+        let mut first_child: HashMap<&str, usize> = HashMap::new();
+        for (row, parent) in based_on.iter().enumerate() {
+            if let Some(parent) = parent {
+                first_child.entry(parent).or_insert(row);
+            }
+        }
+
+        // Row whose Reference is the Based On value of `row`, a row without a reference has none
+        let mut parents: Vec<Option<usize>> = (0..df.height())
+            .map(|row| {
+                references[row]?;
+                rows_by_reference
+                    .get(based_on[row]?)?
+                    .iter()
+                    .copied()
+                    .find(|&candidate| candidate != row)
+            })
+            .collect();
+
+        // A version based on itself has no parent
+        let mut cycles: Vec<Vec<usize>> = Vec::new();
+        for row in 0..df.height() {
+            if based_on[row].is_some() && based_on[row] == references[row] {
+                cycles.push(vec![row]);
+                parents[row] = None;
+            }
+        }
+        cycles.extend(find_cycles(&parents));
+
+        if !cycles.is_empty() {
+            let report: Vec<String> = cycles
+                .iter()
+                .map(|cycle| describe_cycle(df, &references, cycle))
+                .collect();
+            match on_cycle {
+                CyclePolicy::Abort => {
+                    return Err(format!(
+                        "{} cycle(s) in the Based On column: {}",
+                        cycles.len(),
+                        report.join("; ")
+                    )
+                    .into());
+                }
+                CyclePolicy::Break => {
+                    for (cycle, description) in cycles.iter().zip(&report) {
+                        // The table is sorted from the most recent version to the oldest one
+                        let oldest = *cycle.iter().max().expect("a cycle is never empty");
+                        parents[oldest] = None;
+                        eprintln!(
+                            "Warning: cycle {} is broken, {} becomes a root",
+                            description,
+                            describe_row(df, &references, oldest)
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(LineageIndex {
+            references,
+            based_on,
+            rows_by_reference,
+            parents,
+            first_child,
+        })
+    }
+
+    // The dataframe is sorted by date in descending order (most recent day -> the oldest day)
+    // Begin linking only if no other versions based on this version have been created subsequently
+    fn can_start_linked_list(&self, row: usize) -> bool {
+        self.references[row]
+            .and_then(|reference| self.first_child.get(reference))
+            .is_none_or(|&child| child >= row)
+    }
+
+    /// Row whose Reference is the Based On value of `row`
+    fn parent_of(&self, row: usize) -> Option<usize> {
+        self.parents[row]
+    }
+}
+
+/// Every version has at most one parent, so each cycle is found by walking up from each version
+/// until reaching a root, a version already checked, or a version of the current walk
+fn find_cycles(parents: &[Option<usize>]) -> Vec<Vec<usize>> {
+    const UNVISITED: u8 = 0;
+    const ON_WALK: u8 = 1;
+    const CHECKED: u8 = 2;
+
+    let mut cycles: Vec<Vec<usize>> = Vec::new();
+    let mut states: Vec<u8> = vec![UNVISITED; parents.len()];
+    for start in 0..parents.len() {
+        let mut walk: Vec<usize> = Vec::new();
+        let mut current = Some(start);
+        while let Some(row) = current {
+            match states[row] {
+                UNVISITED => {
+                    states[row] = ON_WALK;
+                    walk.push(row);
+                    current = parents[row];
+                }
+                ON_WALK => {
+                    let cycle_start = walk
+                        .iter()
+                        .position(|&visited| visited == row)
+                        .expect("the row is on the current walk");
+                    cycles.push(walk[cycle_start..].to_vec());
+                    break;
+                }
+                _ => break,
+            }
+        }
+        for row in walk {
+            states[row] = CHECKED;
+        }
+    }
+    cycles
+}
+
+/// `B13264R-B (code.xls row 12)`, the location is left out when the table doesn't have it
+pub(crate) fn describe_row(df: &DataFrame, references: &[Option<&str>], row: usize) -> String {
+    let reference = references[row].unwrap_or("<empty>");
+    let source = df
+        .column("Source")
+        .ok()
+        .and_then(|source| source.str().ok()?.get(row).map(str::to_owned));
+    let excel_row = df
+        .column("Excel Row")
+        .ok()
+        .and_then(|excel_row| excel_row.cast(&DataType::UInt32).ok()?.u32().ok()?.get(row));
+    match (source, excel_row) {
+        (Some(source), Some(excel_row)) => format!("{} ({} row {})", reference, source, excel_row),
+        _ => reference.to_owned(),
+    }
+}
+
+/// `A (code.xls row 3) -> B (code.xls row 5) -> A`, each version is based on the next one
+fn describe_cycle(df: &DataFrame, references: &[Option<&str>], cycle: &[usize]) -> String {
+    let mut steps: Vec<String> = cycle
+        .iter()
+        .map(|&row| describe_row(df, references, row))
+        .collect();
+    steps.push(references[cycle[0]].unwrap_or("<empty>").to_owned());
+    steps.join(" -> ")
+}
+
+/// Table written by `prepare-tables` in the parquets folder
+pub const LINEAGE_FILE: &str = "lineage.parquet";
+
+/// Reference of the version
+pub const REFERENCE: &str = "reference";
+/// Reference of the version it is based on, empty for the root of a lineage
+pub const PARENT_REFERENCE: &str = "parent_reference";
+/// Reference of the first version of the lineage
+pub const LINEAGE_ROOT: &str = "lineage_root";
+/// Number of versions between the version and its lineage root
+pub const DEPTH: &str = "depth";
+/// Number of versions directly based on the version
+pub const CHILDREN_COUNT: &str = "children_count";
+
+/// Versions linked through the Based On column, cycles resolved
+pub struct Lineage<'a> {
+    df: &'a DataFrame,
+    index: LineageIndex<'a>,
+}
+
+/// Problem of the source spreadsheets found while linking the versions
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IssueKind {
+    /// The Based On value matches no Reference
+    DanglingParent,
+    /// Based on no version and no version is based on it
+    IsolatedVersion,
+    /// Part of no linked list although it isn't isolated, e.g. a duplicate Reference whose
+    /// versions are linked to another row
+    DroppedSingleton,
+    /// No Reference, the version is left out of every lineage
+    MissingReference,
+}
+
+impl IssueKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueKind::DanglingParent => "dangling_parent",
+            IssueKind::IsolatedVersion => "isolated_version",
+            IssueKind::DroppedSingleton => "dropped_singleton",
+            IssueKind::MissingReference => "missing_reference",
+        }
+    }
+}
+
+pub struct LineageIssue {
+    pub kind: IssueKind,
+    pub row: usize,
+}
+
+impl<'a> Lineage<'a> {
+    /// `df` must be sorted from the most recent version to the oldest one
+    pub fn new(df: &'a DataFrame, on_cycle: CyclePolicy) -> Result<Self, Box<dyn Error>> {
+        Ok(Lineage {
+            df,
+            index: LineageIndex::new(df, on_cycle)?,
+        })
+    }
+
+    /// Rows of every path from a most recent version down to its root, from the root to the
+    /// most recent version. Chains of a single version are dropped.
+    fn linked_list_rows(&self) -> Vec<Vec<IdxSize>> {
+        let mut linked_lists: Vec<Vec<IdxSize>> = Vec::new();
+        for start in 0..self.df.height() {
+            if !self.index.can_start_linked_list(start) {
+                continue;
+            }
+            // Go from top to bottom of the table, linking rows that satisfy this condition:
+            // Reference value of next row = Based On value of current row
+            let mut rows: Vec<IdxSize> = vec![start as IdxSize];
+            let mut current = start;
+            while let Some(parent) = self.index.parent_of(current) {
+                rows.push(parent as IdxSize);
+                current = parent;
+            }
+            // Only keep the linked lists with at least 2 elements
+            if rows.len() >= 2 {
+                // Reverse the link list (date from old -> recent) to make the root version
+                // go to the first row
+                rows.reverse();
+                linked_lists.push(rows);
+            }
+        }
+        linked_lists
+    }
+
+    /// Follow the Based On column from every most recent version down to its root, each chain is
+    /// returned from the root to the most recent version. Chains of a single version are dropped.
+    pub fn linked_lists(&self) -> PolarsResult<Vec<DataFrame>> {
+        self.linked_list_rows()
+            .into_iter()
+            .map(|rows| self.df.take(&IdxCa::from_vec("rows", rows)))
+            .collect()
+    }
+
+    /// Dangling parents, isolated versions and dropped singletons, in table order. An isolated
+    /// version is also left out of the linked lists but only reported as isolated.
+    pub fn issues(&self) -> Vec<LineageIssue> {
+        let index = &self.index;
+        let height = self.df.height();
+        let mut has_children: Vec<bool> = vec![false; height];
+        for parent in index.parents.iter().flatten() {
+            has_children[*parent] = true;
+        }
+        let mut in_linked_list: Vec<bool> = vec![false; height];
+        for row in self.linked_list_rows().into_iter().flatten() {
+            in_linked_list[row as usize] = true;
+        }
+
+        let mut issues: Vec<LineageIssue> = Vec::new();
+        for row in 0..height {
+            let based_on = index.based_on[row];
+            let isolated = index.parent_of(row).is_none() && !has_children[row];
+            let mut report = |kind: IssueKind| issues.push(LineageIssue { kind, row });
+            if index.references[row].is_none() {
+                report(IssueKind::MissingReference);
+                continue;
+            }
+            if based_on.is_some_and(|parent| !index.rows_by_reference.contains_key(parent)) {
+                report(IssueKind::DanglingParent);
+            }
+            if isolated {
+                report(IssueKind::IsolatedVersion);
+            } else if !in_linked_list[row] {
+                report(IssueKind::DroppedSingleton);
+            }
+        }
+        issues
+    }
+
+    /// One row per version with its parent, its lineage root, its depth and its number of
+    /// children, followed by the other columns of the version. Rows are sorted by lineage root,
+    /// then from the root to the leaves, so a parent always comes before its children.
+    pub fn table(&self) -> PolarsResult<DataFrame> {
+        let index = &self.index;
+        let df = self.df;
+        let height = df.height();
+        let parents = &index.parents;
+
+        let mut children_count: Vec<u32> = vec![0; height];
+        for parent in parents.iter().flatten() {
+            children_count[*parent] += 1;
+        }
+
+        // Walk up to the first version whose depth is known, then fill the depths on the way back
+        let mut depths: Vec<Option<u32>> = vec![None; height];
+        let mut roots: Vec<usize> = (0..height).collect();
+        for row in 0..height {
+            let mut path: Vec<usize> = Vec::new();
+            let mut current = row;
+            while depths[current].is_none() {
+                match parents[current] {
+                    Some(parent) => {
+                        path.push(current);
+                        current = parent;
+                    }
+                    None => depths[current] = Some(0),
+                }
+            }
+            while let Some(child) = path.pop() {
+                let parent = parents[child].expect("a version on the path has a parent");
+                depths[child] = depths[parent].map(|depth| depth + 1);
+                roots[child] = roots[parent];
+            }
+        }
+
+        let parent_references: Vec<Option<&str>> = parents
+            .iter()
+            .map(|parent| parent.and_then(|parent| index.references[parent]))
+            .collect();
+        let lineage_roots: Vec<Option<&str>> =
+            roots.iter().map(|root| index.references[*root]).collect();
+        let depths: Vec<u32> = depths.into_iter().map(Option::unwrap_or_default).collect();
+
+        let mut columns = vec![
+            Series::new(REFERENCE, &index.references),
+            Series::new(PARENT_REFERENCE, parent_references),
+            Series::new(LINEAGE_ROOT, lineage_roots),
+            Series::new(DEPTH, depths),
+            Series::new(CHILDREN_COUNT, children_count),
+        ];
+        columns.extend(
+            df.get_columns()
+                .iter()
+                .filter(|series| series.name() != "Reference")
+                .cloned(),
+        );
+
+        DataFrame::new(columns)?
+            .lazy()
+            .sort_by_exprs(
+                [
+                    col(LINEAGE_ROOT),
+                    col(DEPTH),
+                    col("Creation Date"),
+                    col("Id"),
+                ],
+                [false, false, false, false],
+                true,
+                false,
+            )
+            .collect()
+    }
+}
+
+/// Every version of every lineage, as written in `parquets_dir`
+pub fn read_lineage_table(parquets_dir: &Path) -> Result<DataFrame, Box<dyn Error>> {
+    let lineage_path = parquets_dir.join(LINEAGE_FILE);
+    if !lineage_path.is_file() {
+        return Err(format!(
+            "Lineage table {} not found, run prepare-tables first",
+            lineage_path.display()
+        )
+        .into());
+    }
+    Ok(ParquetReader::new(File::open(&lineage_path)?).finish()?)
+}
+
+/// Versions of one lineage, from the root to the leaves
+pub fn read_lineage(
+    parquets_dir: &Path,
+    base_reference: &str,
+) -> Result<DataFrame, Box<dyn Error>> {
+    let df = read_lineage_table(parquets_dir)?
+        .lazy()
+        .filter(col(LINEAGE_ROOT).eq(lit(base_reference)))
+        .collect()?;
+    if df.height() == 0 {
+        return Err(format!(
+            "{} is not the root of any lineage in {}",
+            base_reference,
+            parquets_dir.join(LINEAGE_FILE).display()
+        )
+        .into());
+    }
+    Ok(df)
+}
+
+/// Commit of one version: on top of the commit of the `parent` row, on a branch of its own when
+/// `new_branch` is set. The root has no parent and starts the first branch.
+pub struct ImportStep {
+    pub row: usize,
+    pub parent: Option<usize>,
+    pub new_branch: bool,
+}
+
+/// Depth-first walk of the lineage: the oldest child of a version continues the branch of that
+/// version, each other child starts a new branch from it
+pub fn import_order(df: &DataFrame) -> PolarsResult<Vec<ImportStep>> {
+    let references = df.column(REFERENCE)?.str()?;
+    let parent_references = df.column(PARENT_REFERENCE)?.str()?;
+
+    let mut rows_by_reference: HashMap<&str, usize> = HashMap::new();
+    for (row, reference) in references.into_iter().enumerate() {
+        if let Some(reference) = reference {
+            rows_by_reference.entry(reference).or_insert(row);
+        }
+    }
+    // The table is sorted from the root to the leaves, then by date: children come oldest first
+    let mut roots: Vec<usize> = Vec::new();
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (row, parent_reference) in parent_references.into_iter().enumerate() {
+        match parent_reference.and_then(|parent| rows_by_reference.get(parent)) {
+            Some(&parent) => children.entry(parent).or_default().push(row),
+            None => roots.push(row),
+        }
+    }
+
+    let mut steps: Vec<ImportStep> = Vec::with_capacity(df.height());
+    let mut stack: Vec<ImportStep> = roots
+        .into_iter()
+        .rev()
+        .map(|row| ImportStep {
+            row,
+            parent: None,
+            new_branch: true,
+        })
+        .collect();
+    while let Some(step) = stack.pop() {
+        if let Some(children) = children.get(&step.row) {
+            // Pushed in reverse so that the oldest child is imported right after its parent
+            for (rank, &child) in children.iter().enumerate().rev() {
+                stack.push(ImportStep {
+                    row: child,
+                    parent: Some(step.row),
+                    new_branch: rank > 0,
+                });
+            }
+        }
+        steps.push(step);
+    }
+    Ok(steps)
+}
@@ -0,0 +1,280 @@
+//! Commit messages built from templates over the columns of the lineage table.
+//!
+//! `{Column}` is replaced by the cell of the version in that column, dates as `YYYY-MM-DD HH:MM`,
+//! and `{Column|text}` falls back to `text` when the cell is empty. The subject is the first
+//! line of its template, the other lines and the body template are wrapped into the body. The
+//! trailers follow the body, a trailer whose placeholders are all empty is left out.
+
+use polars::prelude as pl;
+use std::collections::HashMap;
+use std::error::Error;
+
+pub const DEFAULT_SUBJECT: &str = "[{reference}] {Comments|no_comment_found}";
+pub const DEFAULT_WIDTH: usize = 72;
+
+enum Segment {
+    Text(String),
+    Column {
+        name: String,
+        fallback: Option<String>,
+    },
+}
+
+/// A template parsed once, rendered for every version
+struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    fn parse(template: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_owned()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed `{{` in template `{}`", template))?;
+            let placeholder = &rest[start + 1..start + end];
+            let (name, fallback) = match placeholder.split_once('|') {
+                Some((name, fallback)) => (name, Some(fallback.to_owned())),
+                None => (placeholder, None),
+            };
+            if name.trim().is_empty() {
+                return Err(format!("empty placeholder in template `{}`", template));
+            }
+            segments.push(Segment::Column {
+                name: name.trim().to_owned(),
+                fallback,
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_owned()));
+        }
+        Ok(Template { segments })
+    }
+
+    fn columns(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Column { name, .. } => Some(name.as_str()),
+            Segment::Text(_) => None,
+        })
+    }
+
+    /// The rendered text, `None` when every placeholder is empty and has no fallback
+    fn render(&self, cells: &MessageCells, row: usize) -> Result<Option<String>, Box<dyn Error>> {
+        let mut text = String::new();
+        let mut filled = self.columns().next().is_none();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(value) => text.push_str(value),
+                Segment::Column { name, fallback } => {
+                    if let Some(value) = cells.text(name, row)?.or_else(|| fallback.clone()) {
+                        filled = true;
+                        text.push_str(&value);
+                    }
+                }
+            }
+        }
+        Ok(filled.then_some(text))
+    }
+}
+
+/// Subject, body and trailers of the commit messages
+pub struct MessageTemplate {
+    subject: Template,
+    body: Option<Template>,
+    trailers: Vec<Template>,
+    /// Longer subjects are cut with `…`, the part cut off opens the body
+    subject_width: usize,
+    body_width: usize,
+}
+
+impl Default for MessageTemplate {
+    fn default() -> Self {
+        MessageTemplate::new(DEFAULT_SUBJECT, None, &[], DEFAULT_WIDTH, DEFAULT_WIDTH)
+            .expect("the default template is valid")
+    }
+}
+
+impl MessageTemplate {
+    pub fn new(
+        subject: &str,
+        body: Option<&str>,
+        trailers: &[String],
+        subject_width: usize,
+        body_width: usize,
+    ) -> Result<Self, String> {
+        if subject_width < 2 || body_width < 2 {
+            return Err("subject_width and body_width must be at least 2".to_owned());
+        }
+        Ok(MessageTemplate {
+            subject: Template::parse(subject)?,
+            body: body.map(Template::parse).transpose()?,
+            trailers: trailers
+                .iter()
+                .map(|trailer| match trailer.split_once(':') {
+                    Some((token, _)) if !token.trim().is_empty() => Template::parse(trailer),
+                    _ => Err(format!("trailer `{}` is not `Token: value`", trailer)),
+                })
+                .collect::<Result<_, _>>()?,
+            subject_width,
+            body_width,
+        })
+    }
+
+    /// Fail before the first commit when a placeholder names no column of `df`
+    pub fn check_columns(&self, df: &pl::DataFrame) -> Result<(), Box<dyn Error>> {
+        let columns = df.get_column_names();
+        let mut unknown: Vec<&str> = self
+            .subject
+            .columns()
+            .chain(self.body.iter().flat_map(Template::columns))
+            .chain(self.trailers.iter().flat_map(Template::columns))
+            .filter(|name| !columns.contains(name))
+            .collect();
+        unknown.sort();
+        unknown.dedup();
+        match unknown.is_empty() {
+            true => Ok(()),
+            false => Err(format!(
+                "Unknown column(s) in the commit message template: {} (columns: {})",
+                unknown.join(", "),
+                columns.join(", ")
+            )
+            .into()),
+        }
+    }
+
+    /// Columns of `df` the templates read, cast once for every version
+    pub fn cells(&self, df: &pl::DataFrame) -> Result<MessageCells, Box<dyn Error>> {
+        let mut columns = HashMap::new();
+        for name in self
+            .subject
+            .columns()
+            .chain(self.body.iter().flat_map(Template::columns))
+            .chain(self.trailers.iter().flat_map(Template::columns))
+        {
+            if columns.contains_key(name) {
+                continue;
+            }
+            let series = df.column(name)?;
+            let series = match series.dtype() {
+                pl::DataType::Datetime(_, _) | pl::DataType::Date => {
+                    series.cast(&pl::DataType::Datetime(pl::TimeUnit::Milliseconds, None))?
+                }
+                _ => series.cast(&pl::DataType::String)?,
+            };
+            columns.insert(name.to_owned(), series);
+        }
+        Ok(MessageCells { columns })
+    }
+
+    /// Commit message of the version in `row` of the table of `cells`
+    pub fn render(&self, cells: &MessageCells, row: usize) -> Result<String, Box<dyn Error>> {
+        let subject = self.subject.render(cells, row)?.unwrap_or_default();
+        let mut lines = subject.lines();
+        let first_line = lines.next().unwrap_or("").trim_end();
+
+        // A subject too long is cut, what is cut off starts the body
+        let (subject, cut_off) = if first_line.chars().count() > self.subject_width {
+            let cut: String = first_line.chars().take(self.subject_width - 1).collect();
+            let cut_off: String = first_line.chars().skip(self.subject_width - 1).collect();
+            (
+                format!("{}…", cut.trim_end()),
+                Some(format!("…{}", cut_off.trim_start())),
+            )
+        } else {
+            (first_line.to_owned(), None)
+        };
+        let continued: Vec<&str> = cut_off.iter().map(String::as_str).chain(lines).collect();
+        let mut body: Vec<String> = Vec::new();
+        if !continued.is_empty() {
+            body.push(continued.join("\n"));
+        }
+        if let Some(template) = &self.body {
+            body.extend(template.render(cells, row)?);
+        }
+        let body: Vec<String> = body
+            .iter()
+            .map(|paragraph| wrap(paragraph.trim(), self.body_width))
+            .filter(|paragraph| !paragraph.is_empty())
+            .collect();
+
+        let mut trailers: Vec<String> = Vec::new();
+        for trailer in &self.trailers {
+            if let Some(trailer) = trailer.render(cells, row)? {
+                trailers.push(trailer.lines().collect::<Vec<_>>().join(" "));
+            }
+        }
+
+        let mut message = subject;
+        for block in body
+            .into_iter()
+            .chain((!trailers.is_empty()).then(|| trailers.join("\n")))
+        {
+            message.push_str("\n\n");
+            message.push_str(&block);
+        }
+        Ok(message)
+    }
+}
+
+/// `message` with `trailer` added to its trailers, in a new paragraph when it has none
+pub fn append_trailer(message: &str, trailer: &str) -> String {
+    let has_trailers = message
+        .rsplit_once("\n\n")
+        .is_some_and(|(_, last)| last.lines().all(is_trailer));
+    match has_trailers {
+        true => format!("{}\n{}", message, trailer),
+        false => format!("{}\n\n{}", message, trailer),
+    }
+}
+
+/// `Token: value`, the token has no space
+fn is_trailer(line: &str) -> bool {
+    line.split_once(": ")
+        .is_some_and(|(token, _)| !token.is_empty() && !token.contains(char::is_whitespace))
+}
+
+/// Columns read by the templates: dates in milliseconds, the others as text
+pub struct MessageCells {
+    columns: HashMap<String, pl::Series>,
+}
+
+impl MessageCells {
+    /// Text of a cell, `None` when it is empty
+    fn text(&self, column: &str, row: usize) -> Result<Option<String>, Box<dyn Error>> {
+        let series = &self.columns[column];
+        let text = match series.dtype() {
+            pl::DataType::Datetime(_, _) => series
+                .datetime()?
+                .get(row)
+                .and_then(chrono::DateTime::from_timestamp_millis)
+                .map(|date| date.format("%Y-%m-%d %H:%M").to_string()),
+            _ => series.str()?.get(row).map(str::to_owned),
+        };
+        Ok(text.filter(|text| !text.trim().is_empty()))
+    }
+}
+
+/// Wrap every line of `text` at `width` characters, on spaces
+fn wrap(text: &str, width: usize) -> String {
+    let mut wrapped: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let mut current = String::new();
+        for word in line.split_whitespace() {
+            if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+                wrapped.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        wrapped.push(current);
+    }
+    wrapped.join("\n")
+}
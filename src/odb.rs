@@ -0,0 +1,110 @@
+//! Write the versions straight into the object database: the files of the sources are streamed
+//! into blobs, the trees are built with a `TreeBuilder` and the commits land on their branch
+//! without a checkout, so the repository may be bare.
+
+use crate::archive::for_each_project_file;
+use crate::git::{git_commit_tree, GitInfo};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io;
+use std::path::Path;
+
+/// Mode of a folder in a tree
+const TREE: i32 = 0o040000;
+
+/// Content of a folder of the project, by name
+#[derive(Default)]
+struct Folder {
+    entries: BTreeMap<String, Entry>,
+}
+
+enum Entry {
+    File { blob: git2::Oid, mode: i32 },
+    Folder(Folder),
+}
+
+impl Folder {
+    fn insert(&mut self, components: &[String], blob: git2::Oid, mode: i32) {
+        match components {
+            [] => {}
+            [name] => {
+                self.entries
+                    .insert(name.clone(), Entry::File { blob, mode });
+            }
+            [name, rest @ ..] => {
+                let entry = self
+                    .entries
+                    .entry(name.clone())
+                    .or_insert_with(|| Entry::Folder(Folder::default()));
+                if let Entry::Folder(folder) = entry {
+                    folder.insert(rest, blob, mode);
+                }
+            }
+        }
+    }
+
+    fn write(&self, repo: &git2::Repository) -> Result<git2::Oid, git2::Error> {
+        let mut builder = repo.treebuilder(None)?;
+        for (name, entry) in &self.entries {
+            match entry {
+                Entry::File { blob, mode } => builder.insert(name, *blob, *mode)?,
+                Entry::Folder(folder) => builder.insert(name, folder.write(repo)?, TREE)?,
+            };
+        }
+        builder.write()
+    }
+}
+
+/// Tree of the project found in the zip, tar archive or folder, its files are streamed into blobs
+pub fn zip_to_tree(repo: &git2::Repository, zip_file: &Path) -> Result<git2::Oid, Box<dyn Error>> {
+    let mut root = Folder::default();
+    for_each_project_file(zip_file, |path, mode, content| {
+        let mut writer = repo.blob_writer(None)?;
+        io::copy(content, &mut writer)?;
+        root.insert(path, writer.commit()?, mode);
+        Ok(())
+    })?;
+    Ok(root.write(repo)?)
+}
+
+/// Empty "First init" commit starting the root branch `branch`
+pub fn first_init(
+    repo_path: &Path,
+    git_info: &GitInfo,
+    branch: &str,
+) -> Result<String, git2::Error> {
+    let repo = git2::Repository::open(repo_path)?;
+    let signature = git_info.signature()?;
+    let tree = repo.find_tree(repo.treebuilder(None)?.write()?)?;
+    let commit_id = repo.commit(
+        Some(&format!("refs/heads/{}", branch)),
+        &signature,
+        &signature,
+        "First init",
+        &tree,
+        &[],
+    )?;
+    println!("Branch {} is created.", branch);
+    Ok(commit_id.to_string())
+}
+
+/// Commit the sources of `path_to_zip` on `branch`, created at `parent` when it doesn't exist,
+/// and tag the commit with the reference of the version
+pub fn zip_to_commit(
+    repo_path: &Path,
+    git_info: &GitInfo,
+    branch: &str,
+    parent: &str,
+    path_to_zip: &Path,
+) -> Result<String, Box<dyn Error>> {
+    let repo = git2::Repository::open(repo_path)?;
+    println!("Reading {} ", path_to_zip.display());
+    let tree = zip_to_tree(&repo, path_to_zip)?;
+    Ok(git_commit_tree(
+        repo_path,
+        git_info,
+        branch,
+        parent,
+        Some(tree),
+    )?)
+}
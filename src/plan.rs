@@ -0,0 +1,815 @@
+//! What a push would do, computed without touching the repository: `push` executes this plan.
+
+use crate::archive::{files_digest, newest_file_time, FileTime, SourceIndex};
+use crate::authors::AuthorMap;
+use crate::config::PushSettings;
+use crate::git::{branch_heads, find_tagged_commit, first_parent, GitInfo};
+use crate::lineage::{import_order, read_lineage, read_lineage_table, REFERENCE};
+use crate::message::{append_trailer, MessageCells};
+use crate::push::Backend;
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use polars::prelude as pl;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Output of `push-to-git plan`
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PlanFormat {
+    /// Readable summary, lineage by lineage
+    Text,
+    /// Every field of every planned commit, for scripts
+    Json,
+}
+
+/// Every commit of every lineage, in the order they are created
+#[derive(Serialize)]
+pub struct Plan {
+    pub repo_path: PathBuf,
+    /// The repository is deleted first, otherwise the versions without a tag are appended
+    pub rebuild: bool,
+    pub backend: Backend,
+    /// A rebuilt repository is bare
+    pub bare: bool,
+    /// File the fast-import stream is written to instead of the repository
+    pub fast_import_output: Option<PathBuf>,
+    pub lineages: Vec<LineagePlan>,
+    /// Sources of the commit dates, by priority: the versions dated by another source than the
+    /// first one are reported
+    pub commit_date: Vec<DateSource>,
+    /// Authors missing from the authors file, committed under an e-mail built from their name
+    pub unmapped_authors: Vec<UnmappedAuthor>,
+    /// Zips of the zip folders whose file name gives no reference
+    pub unmatched_zips: Vec<PathBuf>,
+}
+
+#[derive(Serialize)]
+pub struct UnmappedAuthor {
+    pub name: String,
+    pub email: String,
+    /// Number of versions committed under this identity
+    pub versions: usize,
+}
+
+#[derive(Serialize)]
+pub struct LineagePlan {
+    pub base_reference: String,
+    pub commits: Vec<PlannedCommit>,
+    /// Versions already imported whose parent changed since
+    pub warnings: Vec<String>,
+}
+
+/// Where the sources of a version come from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ZipSource {
+    /// The zip named after the version
+    Own,
+    /// No zip of its own: the zip of the version it is based on, `no_zip` of the settings
+    /// decides what becomes of the version
+    Previous,
+    /// A root without a zip of its own: the default zip of the base reference
+    Default,
+    /// The zip named after the version, holding the same files as the sources of the version it
+    /// is based on: `no_zip` of the settings decides what becomes of the version
+    Unchanged,
+}
+
+/// What becomes of a version without a zip of its own, or whose zip holds the same files as
+/// the sources of its parent: either way, its tree is the tree of its parent
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum NoZipPolicy {
+    /// A commit keeping the tree of its parent, with a `Source-Archive` trailer
+    #[default]
+    EmptyCommit,
+    /// No commit: the tag of the version points to the commit of its parent, its message has
+    /// the `Source-Archive` trailer
+    TagParent,
+    /// Neither commit nor tag, the versions based on it are committed on top of its parent
+    Skip,
+}
+
+impl NoZipPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NoZipPolicy::EmptyCommit => "empty-commit",
+            NoZipPolicy::TagParent => "tag-parent",
+            NoZipPolicy::Skip => "skip",
+        }
+    }
+}
+
+/// Trailer of the commits and tags of the versions without a zip of their own, the roots
+/// committed with the default zip included
+pub const NO_SOURCE_ARCHIVE: &str = "Source-Archive: none";
+
+/// Trailer of the commits and tags of the versions whose zip holds the same files as the sources
+/// of their parent
+pub const UNCHANGED_SOURCE_ARCHIVE: &str = "Source-Archive: unchanged";
+
+/// Where the date of a commit comes from, `commit_date` of the settings lists them by priority
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum DateSource {
+    CreationDate,
+    ArchiveDate,
+    ExpeditionDate,
+    /// Newest file of the zip, tar archive or folder of the version, only when it has one of its
+    /// own
+    ZipMtime,
+    /// One second after the commit of the version it is based on
+    Parent,
+}
+
+/// Every source, in the order they are tried by default
+pub const DATE_SOURCES: [DateSource; 5] = [
+    DateSource::CreationDate,
+    DateSource::ArchiveDate,
+    DateSource::ExpeditionDate,
+    DateSource::ZipMtime,
+    DateSource::Parent,
+];
+
+impl DateSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DateSource::CreationDate => "creation-date",
+            DateSource::ArchiveDate => "archive-date",
+            DateSource::ExpeditionDate => "expedition-date",
+            DateSource::ZipMtime => "zip-mtime",
+            DateSource::Parent => "parent",
+        }
+    }
+
+    /// Column of the lineage table holding the date
+    fn column(&self) -> Option<&'static str> {
+        match self {
+            DateSource::CreationDate => Some("Creation Date"),
+            DateSource::ArchiveDate => Some("Archive Date"),
+            DateSource::ExpeditionDate => Some("Expedition Date"),
+            DateSource::ZipMtime | DateSource::Parent => None,
+        }
+    }
+}
+
+/// Commit a planned version is based on
+#[derive(Clone, PartialEq, Eq)]
+enum CommitRef {
+    /// Already in the repository
+    Existing(String),
+    /// Created earlier in the plan
+    Planned { lineage: usize, index: usize },
+}
+
+#[derive(Serialize)]
+pub struct PlannedCommit {
+    /// Reference of the version, also the name of its tag
+    pub reference: String,
+    /// Reference of the version it is based on, none for the root of the lineage
+    pub parent: Option<String>,
+    /// Branch the commit lands on
+    pub branch: String,
+    /// The branch is created for this commit
+    pub new_branch: bool,
+    pub author_name: String,
+    pub author_email: String,
+    /// Unix timestamp of the commit, in seconds
+    pub commit_time: Option<i64>,
+    /// Offset of the time zone at `commit_time`, in minutes
+    pub utc_offset: i32,
+    /// Same instant as `commit_time`, in RFC 3339 with the offset
+    pub date: Option<String>,
+    /// First source of the `commit_date` chain that knows the date, `None` fails the plan
+    pub date_source: Option<DateSource>,
+    pub message: String,
+    pub zip: PathBuf,
+    pub zip_source: ZipSource,
+    /// What becomes of the version, when it is based on a version and has no zip of its own or
+    /// the same files as its parent
+    pub no_zip: Option<NoZipPolicy>,
+    /// Tagged by a previous import, its commit is kept as it is
+    pub already_imported: bool,
+    #[serde(skip)]
+    parent_commit: Option<CommitRef>,
+}
+
+impl Plan {
+    pub fn render(&self, format: PlanFormat) -> Result<String, Box<dyn Error>> {
+        match format {
+            PlanFormat::Text => Ok(self.to_string()),
+            PlanFormat::Json => Ok(serde_json::to_string_pretty(self)? + "\n"),
+        }
+    }
+
+    /// Versions to commit whose date doesn't come from the first source of `commit_date`
+    pub fn date_fallbacks(&self) -> Vec<&PlannedCommit> {
+        self.lineages
+            .iter()
+            .flat_map(|lineage| &lineage.commits)
+            .filter(|commit| {
+                !commit.already_imported && commit.date_source != self.commit_date.first().copied()
+            })
+            .collect()
+    }
+}
+
+impl PlannedCommit {
+    pub fn git_info(&self) -> GitInfo {
+        GitInfo::new(
+            self.commit_time,
+            self.utc_offset,
+            self.message.clone(),
+            self.author_name.clone(),
+            self.author_email.clone(),
+            self.reference.clone(),
+        )
+    }
+
+    /// Id of the commit this version is based on, `commits` holds the ids of the commits
+    /// created so far, lineage by lineage
+    pub fn parent_commit_id<'a>(&'a self, commits: &'a [Vec<String>]) -> Option<&'a str> {
+        self.parent_commit.as_ref().map(|parent| match parent {
+            CommitRef::Existing(commit_id) => commit_id.as_str(),
+            CommitRef::Planned { lineage, index } => commits[*lineage][*index].as_str(),
+        })
+    }
+}
+
+/// Plan the import of every base reference of `settings`, the repository is only read
+pub fn plan(settings: &PushSettings) -> Result<Plan, Box<dyn Error>> {
+    let repo_path = &settings.tables.repo_path;
+    // An incremental push deletes nothing: it only starts a repository where there is none
+    let rebuild = match settings.incremental {
+        false => true,
+        true if !repo_path.exists() => true,
+        true => {
+            git2::Repository::open(repo_path).map_err(|e| {
+                format!(
+                    "{} is not a git repository the incremental push can append to: {}",
+                    repo_path.display(),
+                    e.message()
+                )
+            })?;
+            false
+        }
+    };
+    // Head of every branch, as it will be once the previous commits of the plan are created
+    let mut heads: HashMap<String, CommitRef> = HashMap::new();
+    if !rebuild {
+        for (branch, commit_id) in branch_heads(repo_path)? {
+            heads.insert(branch, CommitRef::Existing(commit_id));
+        }
+    }
+
+    let authors = settings.author_map()?;
+    // A folder of the zip folders holds sources when it is named after a version
+    let lineage_table = read_lineage_table(&settings.tables.parquets_dir)?;
+    let references: HashSet<&str> = lineage_table
+        .column(REFERENCE)?
+        .str()?
+        .into_iter()
+        .flatten()
+        .collect();
+    let zips = SourceIndex::build(
+        &settings.zip_folders,
+        &settings.zip_name_pattern,
+        |reference| references.contains(reference),
+    )?;
+
+    let mut lineages: Vec<LineagePlan> = Vec::new();
+    for base_reference in &settings.tables.base_references {
+        let lineage = plan_base_reference(
+            settings,
+            &authors,
+            &zips,
+            base_reference,
+            rebuild,
+            lineages.len(),
+            &mut heads,
+        )?;
+        lineages.push(lineage);
+    }
+
+    let mut unmapped: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+    for commit in lineages.iter().flat_map(|lineage| &lineage.commits) {
+        if authors.find(&commit.author_name).is_none() {
+            *unmapped
+                .entry((&commit.author_name, &commit.author_email))
+                .or_default() += 1;
+        }
+    }
+    let unmapped_authors = unmapped
+        .into_iter()
+        .map(|((name, email), versions)| UnmappedAuthor {
+            name: name.to_owned(),
+            email: email.to_owned(),
+            versions,
+        })
+        .collect();
+    Ok(Plan {
+        repo_path: repo_path.clone(),
+        rebuild,
+        backend: settings.backend,
+        bare: settings.bare,
+        fast_import_output: settings.fast_import_output.clone(),
+        lineages,
+        commit_date: settings.commit_date.clone(),
+        unmapped_authors,
+        unmatched_zips: zips.unmatched,
+    })
+}
+
+/// Plan the lineage of `base_reference`: its root starts a new root branch, the oldest version
+/// based on a version continues its branch and every other one starts a branch of its own
+fn plan_base_reference(
+    settings: &PushSettings,
+    authors: &AuthorMap,
+    zips: &SourceIndex,
+    base_reference: &str,
+    rebuild: bool,
+    lineage: usize,
+    heads: &mut HashMap<String, CommitRef>,
+) -> Result<LineagePlan, Box<dyn Error>> {
+    let repo_path = &settings.tables.repo_path;
+    // Read the lineage of the base reference, from the root to the most recent versions
+    let df = read_lineage(&settings.tables.parquets_dir, base_reference)?;
+    settings.message.check_columns(&df)?;
+    // Cast once for every version: the columns of the messages and of the dates
+    let cells = settings.message.cells(&df)?;
+    let dates = date_columns(&df, settings)?;
+    let default_zip = settings.default_zip_for(base_reference);
+    // Commit and position in `planned` of every planned row, new branches start from the commit
+    // of their parent
+    let mut commits: HashMap<usize, (CommitRef, usize)> = HashMap::new();
+    let mut planned: Vec<PlannedCommit> = Vec::new();
+    let mut warnings: Vec<String> = Vec::new();
+    let mut undated: Vec<String> = Vec::new();
+    // Digest of the files of every zip read so far
+    let mut digests: HashMap<PathBuf, git2::Oid> = HashMap::new();
+
+    for step in import_order(&df)? {
+        let reference = reference_at(&df, step.row)?;
+        let parent = step.parent.map(|parent| &commits[&parent]);
+        let (zip, zip_source) = locate_zip(
+            &reference,
+            zips,
+            parent.map(|(_, index)| planned[*index].zip.as_path()),
+            &default_zip,
+        );
+        // Its own zip, but the files of its parent: the tree wouldn't change
+        let parent_zip = parent.map(|(_, index)| planned[*index].zip.as_path());
+        let unchanged = match (zip_source, parent_zip) {
+            // The default zip of a root already imported may be gone
+            (ZipSource::Own, Some(parent_zip)) if parent_zip.exists() => {
+                digest_of(&zip, &mut digests)? == digest_of(parent_zip, &mut digests)?
+            }
+            _ => false,
+        };
+        let zip_source = match unchanged {
+            true => ZipSource::Unchanged,
+            false => zip_source,
+        };
+        let no_zip = matches!(zip_source, ZipSource::Previous | ZipSource::Unchanged)
+            .then_some(settings.no_zip);
+        let parent_time = parent.and_then(|(_, index)| planned[*index].commit_time);
+        let own_zip =
+            matches!(zip_source, ZipSource::Own | ZipSource::Unchanged).then_some(zip.as_path());
+        let date = commit_date(&dates, step.row, settings, own_zip, parent_time)?;
+        if date.is_none() {
+            undated.push(reference.clone());
+        }
+        let mut git_info = git_info_of(
+            &df,
+            &cells,
+            step.row,
+            settings,
+            authors,
+            date.map(|(date, _)| date),
+        )?;
+        let trailer = match zip_source {
+            ZipSource::Own => None,
+            ZipSource::Previous | ZipSource::Default => Some(NO_SOURCE_ARCHIVE),
+            ZipSource::Unchanged => Some(UNCHANGED_SOURCE_ARCHIVE),
+        };
+        if let Some(trailer) = trailer {
+            git_info.commit_message = append_trailer(&git_info.commit_message, trailer);
+        }
+
+        let existing = match rebuild {
+            true => None,
+            false => find_tagged_commit(repo_path, &reference)?,
+        };
+        if zip_source == ZipSource::Default && existing.is_none() && !zip.exists() {
+            return Err(format!(
+                "{} has no zip of its own and its default zip {} is missing",
+                reference,
+                zip.display()
+            )
+            .into());
+        }
+        if let (Some(commit_id), Some((CommitRef::Existing(parent_id), _))) = (&existing, parent) {
+            // The tag of a version without a commit of its own points to its parent
+            if commit_id != parent_id
+                && first_parent(repo_path, commit_id)?.as_ref() != Some(parent_id)
+            {
+                warnings.push(format!(
+                    "{} is already imported on top of another version, its commit is left as it is",
+                    reference
+                ));
+            }
+        }
+
+        let index = planned.len();
+        let no_commit = matches!(no_zip, Some(NoZipPolicy::TagParent | NoZipPolicy::Skip));
+        let this_commit = match (&existing, parent) {
+            (Some(commit_id), _) => CommitRef::Existing(commit_id.clone()),
+            // No commit of its own: the versions based on it are based on its parent
+            (None, Some((parent_commit, _))) if no_commit => parent_commit.clone(),
+            (None, _) => CommitRef::Planned { lineage, index },
+        };
+        // The parent is the head of a branch: continue that branch
+        let continued_branch = parent.and_then(|(parent_commit, _)| {
+            let mut branches: Vec<&String> = heads
+                .iter()
+                .filter(|(_, head)| *head == parent_commit)
+                .map(|(branch, _)| branch)
+                .collect();
+            branches.sort();
+            branches.first().map(|branch| branch.to_string())
+        });
+        let (branch, new_branch) = match (&existing, continued_branch) {
+            // Nothing lands on a branch, the one of the parent is shown
+            (None, _) if no_commit => (
+                parent.map_or_else(
+                    || reference.clone(),
+                    |(_, index)| planned[*index].branch.clone(),
+                ),
+                false,
+            ),
+            (None, Some(branch)) => (branch, false),
+            (None, None) => (reference.clone(), true),
+            // Already imported: only the head of its branch matters to the next versions
+            (Some(_), continued) => (continued.unwrap_or_else(|| reference.clone()), false),
+        };
+        if existing.is_none() && !no_commit {
+            heads.insert(branch.clone(), this_commit.clone());
+        }
+
+        planned.push(PlannedCommit {
+            parent: parent.map(|(_, index)| planned[*index].reference.clone()),
+            branch,
+            new_branch,
+            date: git_info
+                .commit_time
+                .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+                .and_then(|date| {
+                    let offset = FixedOffset::east_opt(git_info.utc_offset * 60)?;
+                    Some(date.with_timezone(&offset).to_rfc3339())
+                }),
+            commit_time: git_info.commit_time,
+            utc_offset: git_info.utc_offset,
+            date_source: date.map(|(_, source)| source),
+            author_name: git_info.author_name,
+            author_email: git_info.author_email,
+            message: git_info.commit_message,
+            zip,
+            zip_source,
+            no_zip,
+            already_imported: existing.is_some(),
+            parent_commit: parent.map(|(parent_commit, _)| parent_commit.clone()),
+            reference,
+        });
+        commits.insert(step.row, (this_commit, index));
+    }
+    if !undated.is_empty() {
+        return Err(format!(
+            "No commit date for {} version(s) of {}, none of the sources {} knows it: {}",
+            undated.len(),
+            base_reference,
+            settings
+                .commit_date
+                .iter()
+                .map(DateSource::as_str)
+                .collect::<Vec<_>>()
+                .join(", "),
+            undated.join(", ")
+        )
+        .into());
+    }
+
+    Ok(LineagePlan {
+        base_reference: base_reference.to_owned(),
+        commits: planned,
+        warnings,
+    })
+}
+
+/// Zip of `reference` in the index, otherwise the zip of its parent or, for a root, the default
+/// zip
+fn locate_zip(
+    reference: &str,
+    zips: &SourceIndex,
+    parent_zip: Option<&Path>,
+    default_zip: &Path,
+) -> (PathBuf, ZipSource) {
+    match (zips.find(reference), parent_zip) {
+        (Some(zip_path), _) => (zip_path.to_path_buf(), ZipSource::Own),
+        (None, Some(parent_zip)) => (parent_zip.to_path_buf(), ZipSource::Previous),
+        (None, None) => (default_zip.to_path_buf(), ZipSource::Default),
+    }
+}
+
+/// Digest of the files of `zip`, read once
+fn digest_of(
+    zip: &Path,
+    digests: &mut HashMap<PathBuf, git2::Oid>,
+) -> Result<git2::Oid, Box<dyn Error>> {
+    if let Some(digest) = digests.get(zip) {
+        return Ok(*digest);
+    }
+    let digest = files_digest(zip)?;
+    digests.insert(zip.to_path_buf(), digest);
+    Ok(digest)
+}
+
+/// Date of a version and the source it comes from
+type Dated = (DateTime<Tz>, DateSource);
+
+/// Date of the version in `row` from the first source of the `commit_date` chain that knows it
+fn commit_date(
+    dates: &HashMap<&str, pl::Series>,
+    row: usize,
+    settings: &PushSettings,
+    own_zip: Option<&Path>,
+    parent_time: Option<i64>,
+) -> Result<Option<Dated>, Box<dyn Error>> {
+    let time_zone = settings.time_zone;
+    for source in &settings.commit_date {
+        let date = match (source.column(), source) {
+            (Some(column), _) => match dates.get(column) {
+                Some(values) => datetime_seconds(values, row)?
+                    .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+                    .and_then(|wall_clock| local_time(wall_clock.naive_utc(), time_zone)),
+                None => None,
+            },
+            (None, DateSource::ZipMtime) => match own_zip {
+                Some(zip) => match newest_file_time(zip)? {
+                    Some(FileTime::WallClock(wall_clock)) => local_time(wall_clock, time_zone),
+                    Some(FileTime::Instant(instant)) => Some(instant.with_timezone(&time_zone)),
+                    None => None,
+                },
+                None => None,
+            },
+            (None, _) => {
+                parent_time.and_then(|seconds| time_zone.timestamp_opt(seconds + 1, 0).single())
+            }
+        };
+        if let Some(date) = date {
+            return Ok(Some((date, *source)));
+        }
+    }
+    Ok(None)
+}
+
+/// Reference of the version in `row`, `prepare-tables` leaves the versions without one out of
+/// every lineage
+fn reference_at(df: &pl::DataFrame, row: usize) -> Result<String, Box<dyn Error>> {
+    match df.column(REFERENCE)?.str()?.get(row) {
+        Some(reference) => Ok(reference.to_owned()),
+        None => Err(format!("Row {} of the lineage table has no {}", row, REFERENCE).into()),
+    }
+}
+
+/// Commit details of a version of the lineage table, dated `date`
+pub fn git_info_of(
+    df: &pl::DataFrame,
+    cells: &MessageCells,
+    row: usize,
+    settings: &PushSettings,
+    authors: &AuthorMap,
+    date: Option<DateTime<Tz>>,
+) -> Result<GitInfo, Box<dyn Error>> {
+    let unique_reference = reference_at(df, row)?;
+    let author = df
+        .column("Author")?
+        .str()?
+        .get(row)
+        .unwrap_or("no_author_found");
+    let identity = authors.resolve(author);
+    Ok(GitInfo::new(
+        date.map(|date| date.timestamp()),
+        date.map_or(0, |date| date.offset().fix().local_minus_utc() / 60),
+        settings.message.render(cells, row)?,
+        identity.name,
+        identity.email,
+        unique_reference,
+    ))
+}
+
+/// The wall-clock time `wall_clock` in `time_zone`. A time skipped by a change to summer time
+/// is moved an hour later, a time repeated in autumn is the first one.
+fn local_time(wall_clock: NaiveDateTime, time_zone: Tz) -> Option<DateTime<Tz>> {
+    time_zone
+        .from_local_datetime(&wall_clock)
+        .earliest()
+        .or_else(|| {
+            time_zone
+                .from_local_datetime(&(wall_clock + Duration::hours(1)))
+                .earliest()
+        })
+}
+
+/// The date columns of the `commit_date` chain found in `df`, in milliseconds
+fn date_columns(
+    df: &pl::DataFrame,
+    settings: &PushSettings,
+) -> Result<HashMap<&'static str, pl::Series>, pl::PolarsError> {
+    let columns = df.get_column_names();
+    settings
+        .commit_date
+        .iter()
+        .filter_map(DateSource::column)
+        .filter(|column| columns.contains(column))
+        .map(|column| {
+            let values = df
+                .column(column)?
+                .cast(&pl::DataType::Datetime(pl::TimeUnit::Milliseconds, None))?;
+            Ok((column, values))
+        })
+        .collect()
+}
+
+/// Unix timestamp (seconds) of a `Datetime` cell written by `prepare-tables`
+fn datetime_seconds(values: &pl::Series, index: usize) -> Result<Option<i64>, pl::PolarsError> {
+    Ok(values
+        .datetime()?
+        .get(index)
+        .map(|millis| millis.div_euclid(1000)))
+}
+
+impl ZipSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ZipSource::Own => "own",
+            ZipSource::Previous => "previous",
+            ZipSource::Default => "default",
+            ZipSource::Unchanged => "unchanged",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            ZipSource::Own => "",
+            ZipSource::Previous => " (no zip of its own, zip of its parent)",
+            ZipSource::Default => " (no zip of its own, default zip)",
+            ZipSource::Unchanged => " (same files as its parent)",
+        }
+    }
+}
+
+fn parent_of(commit: &PlannedCommit) -> &str {
+    commit.parent.as_deref().unwrap_or("none")
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.rebuild {
+            true => writeln!(f, "{} is deleted and rebuilt", self.repo_path.display())?,
+            false => writeln!(
+                f,
+                "{} is kept, the versions without a tag are appended",
+                self.repo_path.display()
+            )?,
+        }
+        let into = match (&self.fast_import_output, self.bare && self.rebuild) {
+            (Some(output), _) => format!(", into the stream {}", output.display()),
+            (None, true) => ", into a bare repository".to_owned(),
+            (None, false) => String::new(),
+        };
+        writeln!(
+            f,
+            "Sources written by the {} backend{}",
+            self.backend.as_str(),
+            into
+        )?;
+        for lineage in &self.lineages {
+            let created: Vec<&PlannedCommit> = lineage
+                .commits
+                .iter()
+                .filter(|commit| !commit.already_imported)
+                .collect();
+            let branches: Vec<&str> = created
+                .iter()
+                .filter(|commit| commit.new_branch)
+                .map(|commit| commit.branch.as_str())
+                .collect();
+            writeln!(
+                f,
+                "\nLineage {}: {} version(s) to import, {} already imported",
+                lineage.base_reference,
+                created.len(),
+                lineage.commits.len() - created.len()
+            )?;
+            match branches.is_empty() {
+                true => writeln!(f, "Branches to create: none")?,
+                false => writeln!(f, "Branches to create: {}", branches.join(", "))?,
+            }
+            for warning in &lineage.warnings {
+                writeln!(f, "Warning: {}", warning)?;
+            }
+            for commit in &lineage.commits {
+                if commit.already_imported {
+                    writeln!(f, "  {}  already imported, kept", commit.reference)?;
+                    continue;
+                }
+                let position = match (&commit.parent, commit.new_branch) {
+                    (None, _) => format!(
+                        "new root branch {} on an empty \"First init\" commit",
+                        commit.branch
+                    ),
+                    (Some(parent), true) => format!("new branch {} from {}", commit.branch, parent),
+                    (Some(parent), false) => format!("on {} after {}", commit.branch, parent),
+                };
+                let position = match commit.no_zip {
+                    Some(NoZipPolicy::TagParent) => {
+                        format!("no commit, tagged on the commit of {}", parent_of(commit))
+                    }
+                    Some(NoZipPolicy::Skip) => format!(
+                        "skipped, the versions based on it are based on {}",
+                        parent_of(commit)
+                    ),
+                    Some(NoZipPolicy::EmptyCommit) | None => position,
+                };
+                writeln!(f, "  {}  {}", commit.reference, position)?;
+                let fallback = match commit.date_source {
+                    Some(source) if Some(source) != self.commit_date.first().copied() => {
+                        format!(" (from {})", source.as_str())
+                    }
+                    _ => String::new(),
+                };
+                writeln!(
+                    f,
+                    "      {} <{}>, {}{}",
+                    commit.author_name,
+                    commit.author_email,
+                    commit.date.as_deref().unwrap_or("no date"),
+                    fallback
+                )?;
+                // Subject only, the whole message is in the JSON plan
+                writeln!(f, "      {}", commit.message.lines().next().unwrap_or(""))?;
+                match (commit.no_zip, commit.zip_source) {
+                    (Some(policy), ZipSource::Unchanged) => writeln!(
+                        f,
+                        "      zip: {}{}: {}",
+                        commit.zip.display(),
+                        commit.zip_source.describe(),
+                        policy.as_str()
+                    )?,
+                    (Some(policy), _) => {
+                        writeln!(f, "      no zip of its own: {}", policy.as_str())?
+                    }
+                    (None, _) => writeln!(
+                        f,
+                        "      zip: {}{}",
+                        commit.zip.display(),
+                        commit.zip_source.describe()
+                    )?,
+                }
+            }
+        }
+        let fallbacks = self.date_fallbacks();
+        if !fallbacks.is_empty() {
+            writeln!(f, "\nVersions dated by a fallback:")?;
+            for commit in fallbacks {
+                writeln!(
+                    f,
+                    "  {}  {}",
+                    commit.reference,
+                    commit.date_source.map_or("none", |source| source.as_str())
+                )?;
+            }
+        }
+        if !self.unmatched_zips.is_empty() {
+            writeln!(f, "\nZips whose name gives no reference, left out:")?;
+            for zip in &self.unmatched_zips {
+                writeln!(f, "  {}", zip.display())?;
+            }
+        }
+        if !self.unmapped_authors.is_empty() {
+            writeln!(f, "\nAuthors without a mapping:")?;
+            for author in &self.unmapped_authors {
+                writeln!(
+                    f,
+                    "  {} <{}>, {} version(s)",
+                    author.name, author.email, author.versions
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
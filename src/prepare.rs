@@ -0,0 +1,194 @@
+//! First step of the pipeline: from the workbooks to the lineage table.
+
+use crate::config::PrepareSettings;
+use crate::duplicates::resolve_duplicates;
+use crate::ingest::{concat_aligned, convert_excel_to_csv, read_excel_to_dataframe, AddColumn};
+use crate::lineage::{Lineage, CHILDREN_COUNT, DEPTH, LINEAGE_FILE, REFERENCE};
+use crate::quality::write_quality_report;
+use csv::Writer;
+use polars::prelude::*;
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+
+/// Read the workbooks of `settings` and write the tables in its output folder
+pub fn prepare(settings: &PrepareSettings) -> Result<(), Box<dyn Error>> {
+    let parquets_folder_path = &settings.output_dir;
+
+    // Create csv and parquets directories if they don't exist
+    if let Some(csv_folder) = &settings.csv_dir {
+        fs::create_dir_all(csv_folder)?;
+    }
+    fs::create_dir_all(parquets_folder_path)?;
+
+    let mut frames: Vec<DataFrame> = Vec::new();
+    for workbook in &settings.workbooks {
+        // Optional: dump the table read from the workbook, one CSV per workbook
+        if let Some(csv_folder) = &settings.csv_dir {
+            let file_stem = workbook
+                .path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let csv_path = csv_folder.join(format!("{}.csv", file_stem));
+            convert_excel_to_csv(&workbook.path, &csv_path, workbook.logiciel_type)?;
+        }
+
+        let date_system = workbook.date_system()?;
+        let mut lf =
+            read_excel_to_dataframe(&workbook.path, workbook.logiciel_type, date_system)?.lazy();
+
+        // Add reference column
+        lf.add_reference_column(workbook.logiciel_type);
+
+        // remove unnecessary columns
+        let drop_columns: Vec<&str> = workbook.drop_columns.iter().map(String::as_str).collect();
+        let df = lf
+            .filter(
+                col("Target")
+                    .str()
+                    .contains(lit(settings.target_filter.as_str()), false),
+            )
+            .select([col("*").exclude(drop_columns)])
+            .collect()?;
+        frames.push(df);
+    }
+
+    // Construct final DataFrame
+    let df = concat_aligned(&frames)?;
+    let df = df.with_row_count("Id", None)?;
+
+    // Sort by Creation/Archive Date in descending order, the row count breaks the remaining ties
+    let df = df
+        .lazy()
+        .sort_by_exprs(
+            [col("Creation Date"), col("Archive Date"), col("Id")],
+            [true, true, true],
+            true,
+            false,
+        )
+        .collect()?;
+
+    // One row per Reference, the others are kept aside for the audit
+    let deduplicated = resolve_duplicates(
+        &df,
+        settings.on_duplicate,
+        settings.preferred_source.as_deref(),
+    )?;
+    let mut df_duplicates = deduplicated.audit;
+    let df = deduplicated.df;
+
+    // Optional: Make a relation parquet file to see the first link
+    let mut df_relation = df
+        .clone()
+        .lazy()
+        .group_by([col("Based On")])
+        .agg([col("Reference").alias("References")])
+        .collect()?;
+
+    // Link the versions before touching the previous outputs, a cycle may abort the run
+    let lineage = Lineage::new(&df, settings.on_cycle)?;
+    let mut df_lineage = lineage.table()?;
+
+    // Delete old .parquet files
+    match delete_parquet_files_in_directory(parquets_folder_path) {
+        Ok(_) => println!("All .parquet files deleted successfully"),
+        Err(e) => eprintln!("Error deleting files: {}", e),
+    }
+
+    // Optional: Create original DataFrame
+    let original_parquet_path = parquets_folder_path.join("original.parquet");
+    let mut file_original = File::create(original_parquet_path).unwrap();
+    ParquetWriter::new(&mut file_original)
+        .finish(&mut df.clone())
+        .unwrap();
+    // Optional: First Link parquet files
+    let relation_parquet_path = parquets_folder_path.join("relation.parquet");
+    let mut file_relation = File::create(relation_parquet_path).unwrap();
+    ParquetWriter::new(&mut file_relation)
+        .finish(&mut df_relation)
+        .unwrap();
+
+    // Rows left out because another row has the same Reference
+    let duplicates_parquet_path = parquets_folder_path.join("duplicates.parquet");
+    let mut file_duplicates = File::create(&duplicates_parquet_path)?;
+    ParquetWriter::new(&mut file_duplicates).finish(&mut df_duplicates)?;
+
+    // Lineage table: every version with its parent, lineage root, depth and number of children
+    let lineage_parquet_path = parquets_folder_path.join(LINEAGE_FILE);
+    let mut file_lineage = File::create(&lineage_parquet_path)?;
+    ParquetWriter::new(&mut file_lineage).finish(&mut df_lineage)?;
+    println!(
+        "Lineage table of {} versions is created: {}",
+        df_lineage.height(),
+        lineage_parquet_path.display()
+    );
+
+    // Lineage roots with at least one version based on them
+    let df_base_refs = df_lineage
+        .clone()
+        .lazy()
+        .filter(col(DEPTH).eq(lit(0)).and(col(CHILDREN_COUNT).gt(lit(0))))
+        .collect()?;
+    let base_refs: Vec<&str> = df_base_refs
+        .column(REFERENCE)?
+        .str()?
+        .into_iter()
+        .flatten()
+        .collect();
+    vec_to_csv(
+        &base_refs,
+        &parquets_folder_path.join("base-references.csv"),
+    )?;
+
+    // Dangling parents, isolated versions and dropped singletons to fix in the source workbooks
+    write_quality_report(&df, &lineage.issues(), parquets_folder_path)?;
+
+    // Optional: one .parquet file per path from a root to a most recent version
+    if settings.linked_lists {
+        let linked_lists = lineage.linked_lists()?;
+        for (index, list) in linked_lists.iter().enumerate() {
+            let reference = list
+                .column("Reference")
+                .unwrap()
+                .str()
+                .unwrap()
+                .get(0)
+                .unwrap_or("");
+            let file_name =
+                parquets_folder_path.join(format!("{}_{}.parquet", reference, index + 1));
+
+            let mut file = File::create(&file_name).expect("cannot create parquet file");
+            ParquetWriter::new(&mut file)
+                .finish(&mut list.clone())
+                .expect("cannot write parquet file");
+            println!("Linked List {}_{} is created", reference, index + 1);
+        }
+    }
+
+    Ok(())
+}
+
+fn delete_parquet_files_in_directory(directory_path: &Path) -> std::io::Result<()> {
+    let paths = fs::read_dir(directory_path)?;
+    for entry in paths.flatten() {
+        if let Some(extension) = entry.path().extension() {
+            if extension == "parquet" {
+                fs::remove_file(entry.path())?;
+                println!("Deleted file: {:?}", entry.path().display());
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn vec_to_csv<T: ToString>(data: &[T], file_path: &Path) -> Result<(), Box<dyn Error>> {
+    let file = File::create(file_path)?;
+    let mut writer = Writer::from_writer(file);
+    for value in data {
+        writer.write_record(&[value.to_string()])?;
+    }
+    writer.flush()?;
+    println!("Data saved to CSV file: {}", file_path.display());
+    Ok(())
+}
@@ -0,0 +1,380 @@
+//! Second step of the pipeline: from the lineage table to the git repository.
+
+use crate::archive::{delete_folder, extract_source_to_repo};
+use crate::config::{PushSettings, TableSettings};
+use crate::fast_import::{spawn_git_fast_import, FastImport};
+use crate::git::{
+    create_folder_and_init_git_repo, find_tagged_commit, git_add_all, git_commit, git_commit_tree,
+    git_create_and_switch_to_new_branch_from_commit, git_init_and_switch_to_first_branch,
+    git_mark_skipped, git_set_head_to_branch, git_switch_to_branch, git_tag_commit, GitInfo,
+    GitState, SKIPPED_REFS,
+};
+use crate::lineage::{read_lineage, REFERENCE};
+use crate::odb;
+use crate::plan::{plan, NoZipPolicy, Plan, PlannedCommit, ZipSource};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::Child;
+
+/// How the sources of the versions are written into the repository
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Unpack each zip over the working tree, then stage and commit it
+    #[default]
+    Worktree,
+    /// Stream the zip entries into blobs and trees, commit without a checkout
+    Odb,
+    /// Write a `git fast-import` stream, to a file or piped into `git fast-import`
+    FastImport,
+}
+
+impl Backend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Backend::Worktree => "worktree",
+            Backend::Odb => "odb",
+            Backend::FastImport => "fast-import",
+        }
+    }
+}
+
+/// Delete the repository of `settings`, then recreate it with one commit per version. An
+/// incremental push keeps the repository and only commits the versions without a tag.
+pub fn push(settings: &PushSettings) -> Result<(), Box<dyn Error>> {
+    let plan = plan(settings)?;
+    execute(&plan, &settings.temp_dir)
+}
+
+/// Create the commits, branches and tags of `plan`, in its order
+pub fn execute(plan: &Plan, temp_path: &Path) -> Result<(), Box<dyn Error>> {
+    let repo_path = &plan.repo_path;
+    // A fast-import stream written to a file leaves the repository alone
+    if plan.fast_import_output.is_none() {
+        prepare_repo(plan);
+    }
+    if !plan.unmapped_authors.is_empty() {
+        let names: Vec<&str> = plan
+            .unmapped_authors
+            .iter()
+            .map(|author| author.name.as_str())
+            .collect();
+        println!(
+            "{} author(s) without a mapping, e-mails built from their names: {}",
+            names.len(),
+            names.join(", ")
+        );
+    }
+    if !plan.unmatched_zips.is_empty() {
+        println!(
+            "{} zip(s) left out, their name gives no reference",
+            plan.unmatched_zips.len()
+        );
+    }
+    for commit in plan.date_fallbacks() {
+        println!(
+            "{} is dated {} from {}",
+            commit.reference,
+            commit.date.as_deref().unwrap_or("no date"),
+            commit.date_source.map_or("none", |source| source.as_str())
+        );
+    }
+    let (out, mut git_fast_import) = match plan.backend {
+        Backend::FastImport => {
+            let (out, child) = open_fast_import_stream(plan)?;
+            (Some(out), child)
+        }
+        Backend::Worktree | Backend::Odb => (None, None),
+    };
+    let written = write_commits(plan, out, temp_path);
+    if let Some(child) = git_fast_import.as_mut() {
+        // The stream is dropped by now: on an error, stop `git fast-import` before it reads the
+        // end of a stream cut short
+        if written.is_err() {
+            let _ = child.kill();
+        }
+        let status = child.wait()?;
+        match written {
+            Err(e) => return Err(format!("{}, git fast-import is stopped: {}", e, status).into()),
+            Ok(()) if !status.success() => {
+                return Err(format!("git fast-import failed: {}", status).into())
+            }
+            Ok(()) => {}
+        }
+    }
+    written?;
+    if let (Backend::FastImport, Some(output)) = (plan.backend, &plan.fast_import_output) {
+        println!(
+            "Fast-import stream is written: {}, replay it with `git fast-import < {}`",
+            output.display(),
+            output.display()
+        );
+        return Ok(());
+    }
+    // Only the worktree backend moves HEAD: leave it on the last branch with the others too
+    let last_branch = plan
+        .lineages
+        .iter()
+        .flat_map(|lineage| &lineage.commits)
+        .rfind(|commit| !commit.already_imported)
+        .map(|commit| &commit.branch);
+    if let (Backend::Odb | Backend::FastImport, Some(branch)) = (plan.backend, last_branch) {
+        git_set_head_to_branch(repo_path, branch)?;
+    }
+
+    Ok(())
+}
+
+/// Create the commits of `plan`, lineage by lineage, as a fast-import stream written to `out`
+/// when it is given
+fn write_commits(
+    plan: &Plan,
+    out: Option<Box<dyn Write>>,
+    temp_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let repo_path = &plan.repo_path;
+    let mut stream = out.map(FastImport::new).transpose()?;
+    // Commit of every planned version, lineage by lineage
+    let mut commits: Vec<Vec<String>> = Vec::new();
+    for lineage in &plan.lineages {
+        for warning in &lineage.warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        commits.push(Vec::new());
+        for commit in &lineage.commits {
+            let commit_id = if commit.already_imported {
+                println!("{} is already imported, skipped", commit.reference);
+                find_tagged_commit(repo_path, &commit.reference)?
+                    .expect("an imported version lost its tag")
+            } else if let Some(policy) = commit.no_zip {
+                let parent_id = commit
+                    .parent_commit_id(&commits)
+                    .expect("a version without a zip of its own has a parent")
+                    .to_owned();
+                let reason = match commit.zip_source {
+                    ZipSource::Unchanged => "has the same files as its parent",
+                    _ => "has no zip of its own",
+                };
+                println!("{} {}, {}", commit.reference, reason, policy.as_str());
+                let git_info = commit.git_info();
+                match (policy, stream.as_mut()) {
+                    (NoZipPolicy::EmptyCommit, Some(stream)) => {
+                        stream.zip_to_commit(&git_info, &commit.branch, &parent_id, None)?
+                    }
+                    (NoZipPolicy::EmptyCommit, None) => {
+                        git_commit_tree(repo_path, &git_info, &commit.branch, &parent_id, None)?
+                    }
+                    (NoZipPolicy::TagParent, Some(stream)) => {
+                        stream.tag(&git_info, &parent_id, &git_info.commit_message)?;
+                        parent_id
+                    }
+                    (NoZipPolicy::TagParent, None) => {
+                        git_tag_commit(repo_path, &git_info, &parent_id)?;
+                        parent_id
+                    }
+                    (NoZipPolicy::Skip, Some(stream)) => {
+                        stream.mark_skipped(&commit.reference, &parent_id)?;
+                        parent_id
+                    }
+                    (NoZipPolicy::Skip, None) => {
+                        git_mark_skipped(repo_path, &commit.reference, &parent_id)?;
+                        parent_id
+                    }
+                }
+            } else {
+                if commit.zip_source != ZipSource::Own {
+                    println!(
+                        "Can't find any files with reference: {} use the {} zip",
+                        commit.reference,
+                        commit.zip_source.as_str()
+                    );
+                }
+                match stream.as_mut() {
+                    Some(stream) => execute_fast_import_commit(stream, commit, &commits)?,
+                    None if plan.backend == Backend::Odb => {
+                        execute_odb_commit(repo_path, commit, &commits)?
+                    }
+                    None => execute_commit(repo_path, commit, &commits, temp_path)?,
+                }
+            };
+            commits.last_mut().unwrap().push(commit_id);
+        }
+    }
+    if let Some(stream) = stream {
+        drop(stream.finish()?);
+    }
+    Ok(())
+}
+
+/// Delete the repository unless the push is incremental, then create it if needed
+fn prepare_repo(plan: &Plan) {
+    let repo_path = &plan.repo_path;
+    if plan.rebuild && repo_path.exists() {
+        // First delete the old repo folder
+        match delete_folder(repo_path) {
+            Ok(_) => println!("{} is deleted successfully!", repo_path.display()),
+            Err(e) => println!("Error deleting folder {}: {}", repo_path.display(), e),
+        };
+    } else {
+        println!(
+            "Importing the versions without a tag into {}",
+            repo_path.display()
+        );
+    }
+    // git init
+    match create_folder_and_init_git_repo(repo_path, plan.bare) {
+        Ok(GitState::FirstInit) => println!("Git repo initialized successfully!"),
+        Ok(GitState::AlreadyInit) => println!("Git repo already initialized!"),
+        Err(e) => eprintln!("Failed to initialize Git repo: {}", e),
+    };
+}
+
+/// Output of the fast-import stream, and the `git fast-import` reading it
+type StreamOutput = (Box<dyn Write>, Option<Child>);
+
+/// Output of the fast-import stream: the file of the plan, or `git fast-import` run in the
+/// repository
+fn open_fast_import_stream(plan: &Plan) -> Result<StreamOutput, Box<dyn Error>> {
+    match &plan.fast_import_output {
+        Some(output) => {
+            let file = File::create(output)
+                .map_err(|e| format!("Cannot create {}: {}", output.display(), e))?;
+            Ok((Box::new(BufWriter::new(file)), None))
+        }
+        None => {
+            let mut child = spawn_git_fast_import(&plan.repo_path)
+                .map_err(|e| format!("Cannot run git fast-import: {}", e))?;
+            let stdin = child.stdin.take().expect("the standard input is piped");
+            Ok((Box::new(BufWriter::new(stdin)), Some(child)))
+        }
+    }
+}
+
+/// Create one planned commit, `commits` holds the ids of the commits created before it
+fn execute_commit(
+    repo_path: &Path,
+    commit: &PlannedCommit,
+    commits: &[Vec<String>],
+    temp_path: &Path,
+) -> Result<String, Box<dyn Error>> {
+    let git_info = commit.git_info();
+    match commit.parent_commit_id(commits) {
+        None => {
+            // First init, create a initial branch with message `first init`
+            println!("Creating first branch...");
+            git_init_and_switch_to_first_branch(repo_path, &git_info)?;
+        }
+        // create a branch from the commit of the parent version
+        Some(parent_id) if commit.new_branch => {
+            git_create_and_switch_to_new_branch_from_commit(&commit.branch, repo_path, parent_id)?
+        }
+        // The parent is the head of a branch: continue that branch
+        Some(_) => git_switch_to_branch(repo_path, &commit.branch)?,
+    }
+    zip_to_git(repo_path, &git_info, &commit.zip, temp_path)
+}
+
+/// Create one planned commit straight into the objects of the repository, no checkout
+fn execute_odb_commit(
+    repo_path: &Path,
+    commit: &PlannedCommit,
+    commits: &[Vec<String>],
+) -> Result<String, Box<dyn Error>> {
+    let git_info = commit.git_info();
+    let parent_id = match commit.parent_commit_id(commits) {
+        Some(parent_id) => parent_id.to_owned(),
+        // First init of a root branch
+        None => odb::first_init(repo_path, &git_info, &commit.branch)?,
+    };
+    odb::zip_to_commit(
+        repo_path,
+        &git_info,
+        &commit.branch,
+        &parent_id,
+        &commit.zip,
+    )
+}
+
+/// Add one planned commit to the stream, its mark stands for its id
+fn execute_fast_import_commit(
+    stream: &mut FastImport<Box<dyn Write>>,
+    commit: &PlannedCommit,
+    commits: &[Vec<String>],
+) -> Result<String, Box<dyn Error>> {
+    let git_info = commit.git_info();
+    let parent = match commit.parent_commit_id(commits) {
+        Some(parent) => parent.to_owned(),
+        None => stream.first_init(&git_info, &commit.branch)?,
+    };
+    stream.zip_to_commit(&git_info, &commit.branch, &parent, Some(&commit.zip))
+}
+
+/// Check that every version of the lineages has a tag in the repository, or the mark of a
+/// version skipped by `--no-zip skip`
+pub fn verify(settings: &TableSettings) -> Result<(), Box<dyn Error>> {
+    let repo_path = &settings.repo_path;
+    let repo = git2::Repository::open(repo_path)?;
+
+    let mut references: HashSet<String> = HashSet::new();
+    for base_reference in &settings.base_references {
+        let df = read_lineage(&settings.parquets_dir, base_reference)?;
+        for reference in df.column(REFERENCE)?.str()?.into_iter().flatten() {
+            references.insert(reference.to_owned());
+        }
+    }
+
+    let skipped: HashSet<&String> = references
+        .iter()
+        .filter(|reference| {
+            repo.find_reference(&format!("{}{}", SKIPPED_REFS, reference))
+                .is_ok()
+        })
+        .collect();
+    let mut missing: Vec<&String> = references
+        .iter()
+        .filter(|reference| {
+            repo.find_reference(&format!("refs/tags/{}", reference))
+                .is_err()
+                && !skipped.contains(reference)
+        })
+        .collect();
+    missing.sort();
+    for reference in &missing {
+        println!("Missing tag for reference {}", reference);
+    }
+    if !missing.is_empty() {
+        return Err(format!(
+            "{} of {} references are not tagged in {}",
+            missing.len(),
+            references.len(),
+            repo_path.display()
+        )
+        .into());
+    }
+    println!(
+        "All {} references are tagged in {}, {} of them skipped without a tag",
+        references.len(),
+        repo_path.display(),
+        skipped.len()
+    );
+    Ok(())
+}
+
+/// Commit the sources of `path_to_zip` on the current branch
+fn zip_to_git(
+    repo_path: &Path,
+    git_info: &GitInfo,
+    path_to_zip: &Path,
+    temp_path: &Path,
+) -> Result<String, Box<dyn Error>> {
+    println!("Extracting {} ", path_to_zip.display());
+    extract_source_to_repo(path_to_zip, repo_path, temp_path)
+        .map_err(|e| format!("Can't extract {}: {}", path_to_zip.display(), e))?;
+    git_add_all(repo_path).map_err(|e| format!("Git-add error: {}", e))?;
+    Ok(git_commit(repo_path, git_info)?)
+}
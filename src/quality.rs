@@ -0,0 +1,120 @@
+//! Data-quality report of the lineage: dangling parents, isolated versions, dropped singletons,
+//! versions without a reference.
+
+use crate::lineage::{IssueKind, LineageIssue};
+use polars::prelude::*;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// Kind of problem, see [`IssueKind`]
+pub const ISSUE: &str = "issue";
+
+/// Columns of the version copied to the report, when the table has them
+const REPORT_COLUMNS: [&str; 5] = [
+    "Reference",
+    "Based On",
+    "Source",
+    "Excel Row",
+    "Creation Date",
+];
+
+/// One row per issue with the location of the version in the source workbooks
+pub fn build_quality_report(df: &DataFrame, issues: &[LineageIssue]) -> PolarsResult<DataFrame> {
+    let kinds: Vec<&str> = issues.iter().map(|issue| issue.kind.as_str()).collect();
+    let rows: Vec<IdxSize> = issues.iter().map(|issue| issue.row as IdxSize).collect();
+    let columns: Vec<&str> = REPORT_COLUMNS
+        .into_iter()
+        .filter(|name| df.column(name).is_ok())
+        .collect();
+    let versions = df.select(columns)?.take(&IdxCa::from_vec("rows", rows))?;
+
+    let mut report = DataFrame::new(vec![Series::new(ISSUE, kinds)])?;
+    report.hstack_mut(versions.get_columns())?;
+    Ok(report)
+}
+
+/// Counts per kind of issue followed by one line per issue, meant for the data stewards
+pub fn summarize_quality_report(report: &DataFrame) -> PolarsResult<String> {
+    let kinds = report.column(ISSUE)?.str()?;
+    let references = report.column("Reference")?.str()?;
+    let based_on = report.column("Based On")?.str()?;
+    let sources = report
+        .column("Source")
+        .ok()
+        .map(|source| source.str())
+        .transpose()?;
+    let excel_rows = report
+        .column("Excel Row")
+        .ok()
+        .map(|excel_row| excel_row.cast(&DataType::UInt32))
+        .transpose()?;
+    let excel_rows = excel_rows
+        .as_ref()
+        .map(|excel_row| excel_row.u32())
+        .transpose()?;
+
+    let mut summary = format!("{} lineage issue(s)\n", report.height());
+    for kind in [
+        IssueKind::DanglingParent,
+        IssueKind::IsolatedVersion,
+        IssueKind::DroppedSingleton,
+        IssueKind::MissingReference,
+    ] {
+        let rows: Vec<usize> = (0..report.height())
+            .filter(|&row| kinds.get(row) == Some(kind.as_str()))
+            .collect();
+        let _ = writeln!(summary, "\n{}: {}", kind.as_str(), rows.len());
+        for row in rows {
+            let mut line = references.get(row).unwrap_or("<empty>").to_owned();
+            if let (Some(source), Some(excel_row)) = (
+                sources.and_then(|source| source.get(row)),
+                excel_rows.and_then(|excel_row| excel_row.get(row)),
+            ) {
+                let _ = write!(line, " ({} row {})", source, excel_row);
+            }
+            match kind {
+                IssueKind::DanglingParent => {
+                    let _ = write!(
+                        line,
+                        " is based on {}, which matches no Reference",
+                        based_on.get(row).unwrap_or("<empty>")
+                    );
+                }
+                IssueKind::IsolatedVersion => {
+                    line.push_str(" is based on no version and no version is based on it")
+                }
+                IssueKind::DroppedSingleton => line.push_str(" is part of no linked list"),
+                IssueKind::MissingReference => {
+                    line.push_str(" has no Reference and is left out of every lineage")
+                }
+            }
+            let _ = writeln!(summary, "  {}", line);
+        }
+    }
+    Ok(summary)
+}
+
+/// `quality_report.parquet` and `quality_report.txt` in `output_dir`, the summary is also printed
+pub fn write_quality_report(
+    df: &DataFrame,
+    issues: &[LineageIssue],
+    output_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut report = build_quality_report(df, issues)?;
+    let parquet_path = output_dir.join("quality_report.parquet");
+    let mut file = fs::File::create(&parquet_path)?;
+    ParquetWriter::new(&mut file).finish(&mut report)?;
+
+    let summary = summarize_quality_report(&report)?;
+    let summary_path = output_dir.join("quality_report.txt");
+    fs::write(&summary_path, &summary)?;
+    print!("{}", summary);
+    println!(
+        "Data-quality report is created: {}, {}",
+        parquet_path.display(),
+        summary_path.display()
+    );
+    Ok(())
+}
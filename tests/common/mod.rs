@@ -0,0 +1,492 @@
+//! Synthetic exports and source zips, laid out in a temporary folder like a real migration.
+
+#![allow(dead_code)]
+
+use git_push_automatic::config::{
+    PrepareConfig, PrepareSettings, PushConfig, PushSettings, WorkbookConfig,
+};
+use git_push_automatic::ingest::LogicielType;
+use git_push_automatic::plan::Plan;
+use rust_xlsxwriter::{Format, Workbook};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Header of every synthetic export, after the two columns building the `Reference`
+const HEADERS: [&str; 8] = [
+    "Target",
+    "Based On",
+    "Creation Date",
+    "Archive Date",
+    "Expedition Date",
+    "Study Number",
+    "Author",
+    "Comments",
+];
+
+/// One row of an export
+pub struct Row {
+    /// `Software P/N` and `Version` for code, `Préf` and `Number` for etude
+    pub reference: (&'static str, &'static str),
+    pub based_on: &'static str,
+    /// Excel serial numbers of the Creation Date and of the Archive Date, `None` leaves it empty
+    pub created: Option<f64>,
+    pub archived: Option<f64>,
+    pub author: &'static str,
+    pub comment: &'static str,
+}
+
+impl Row {
+    /// The same row with other dates
+    pub fn dates(self, created: Option<f64>, archived: Option<f64>) -> Row {
+        Row {
+            created,
+            archived,
+            ..self
+        }
+    }
+}
+
+pub fn row(
+    reference: (&'static str, &'static str),
+    based_on: &'static str,
+    created: f64,
+    author: &'static str,
+    comment: &'static str,
+) -> Row {
+    Row {
+        reference,
+        based_on,
+        created: Some(created),
+        archived: Some(created + 1.0),
+        author,
+        comment,
+    }
+}
+
+/// Export with a few title rows above the header, as the legacy tool writes them
+pub fn write_workbook(path: &Path, logiciel_type: LogicielType, rows: &[Row]) {
+    let date = Format::new().set_num_format("dd/mm/yyyy hh:mm");
+    write_table(path, logiciel_type, rows, &date);
+}
+
+/// Export of the old Mac tool: 1904 date system, dates left as bare serial numbers
+pub fn write_workbook_1904(path: &Path, logiciel_type: LogicielType, rows: &[Row]) {
+    write_table(path, logiciel_type, rows, &Format::new());
+    let mut archive = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).unwrap();
+        let mut content = Vec::new();
+        std::io::Read::read_to_end(&mut entry, &mut content).unwrap();
+        if entry.name() == "xl/workbook.xml" {
+            content = String::from_utf8(content)
+                .unwrap()
+                .replace("<workbookPr", "<workbookPr date1904=\"1\"")
+                .into_bytes();
+        }
+        entries.push((entry.name().to_owned(), content));
+    }
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    for (name, content) in entries {
+        zip.start_file(name, Default::default()).unwrap();
+        zip.write_all(&content).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+/// .xls file holding only the workbook globals up to their `DATEMODE` record
+pub fn write_xls_date_mode(path: &Path, date_1904: bool) {
+    fs::write(path, xls_date_mode(date_1904, false)).unwrap();
+}
+
+/// Bytes of an .xls file holding only the workbook globals up to their `DATEMODE` record: a
+/// compound file of one FAT sector and one directory sector, the `Workbook` stream in 8 sectors
+/// of its own or, when `small`, in the mini stream
+pub fn xls_date_mode(date_1904: bool, small: bool) -> Vec<u8> {
+    const FREE: u32 = 0xFFFF_FFFF;
+    const END_OF_CHAIN: u32 = 0xFFFF_FFFE;
+    const FAT_SECTOR: u32 = 0xFFFF_FFFD;
+    let u16s =
+        |values: &[u16]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+    let u32s =
+        |values: &[u32]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+    let sector = |mut bytes: Vec<u8>, fill: u8| {
+        bytes.resize(512, fill);
+        bytes
+    };
+
+    // BOF, DATEMODE and EOF records
+    let mut stream = u16s(&[0x0809, 16, 0x0600, 0x0005]);
+    stream.resize(4 + 16, 0);
+    stream.extend(u16s(&[0x0022, 2, date_1904 as u16, 0x000A, 0]));
+    let stream_size = if small { stream.len() } else { 4096 };
+    stream.resize(stream_size, 0);
+
+    // Sectors: the FAT, the directory, then the stream, or the mini stream and the mini FAT
+    let (fat, root_start, mini_fat_start, stream_start) = match small {
+        false => (
+            vec![FAT_SECTOR, END_OF_CHAIN, 3, 4, 5, 6, 7, 8, 9, END_OF_CHAIN],
+            END_OF_CHAIN,
+            END_OF_CHAIN,
+            2,
+        ),
+        true => (
+            vec![FAT_SECTOR, END_OF_CHAIN, END_OF_CHAIN, END_OF_CHAIN],
+            2,
+            3,
+            0,
+        ),
+    };
+    let mut header = vec![0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+    header.resize(0x18, 0);
+    header.extend(u16s(&[0x3E, 3, 0xFFFE, 9, 6]));
+    header.resize(0x2C, 0);
+    header.extend(u32s(&[
+        1,
+        1,
+        0,
+        4096,
+        mini_fat_start,
+        small as u32,
+        END_OF_CHAIN,
+        0,
+        0,
+    ]));
+
+    let entry = |name: &str, kind: u8, start: u32, size: u32| {
+        let mut entry: Vec<u8> = u16s(&name.encode_utf16().chain([0]).collect::<Vec<u16>>());
+        entry.resize(0x40, 0);
+        entry.extend(u16s(&[2 * (name.len() as u16 + 1)]));
+        entry.extend([kind, 1]);
+        entry.extend(u32s(&[FREE, FREE, FREE]));
+        entry.resize(0x74, 0);
+        entry.extend(u32s(&[start, size, 0]));
+        entry
+    };
+    let root_size = if small { 64 } else { 0 };
+    let mut directory = entry("Root Entry", 5, root_start, root_size);
+    directory.extend(entry("Workbook", 2, stream_start, stream_size as u32));
+
+    let mut bytes = [
+        sector(header, 0xFF),
+        sector(u32s(&fat), 0xFF),
+        sector(directory, 0),
+    ]
+    .concat();
+    match small {
+        false => bytes.extend(stream),
+        true => {
+            bytes.extend(sector(stream, 0));
+            bytes.extend(sector(u32s(&[END_OF_CHAIN]), 0xFF));
+        }
+    }
+    bytes
+}
+
+/// .xlsb file whose workbook part holds its `BrtWbProp` record
+pub fn write_xlsb_date_mode(path: &Path, date_1904: bool) {
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    zip.start_file("xl/workbook.bin", Default::default())
+        .unwrap();
+    // BrtBeginBook, then BrtWbProp: 2 bytes of type, 1 of size and 4 bytes of flags
+    zip.write_all(&[0x83, 0x01, 0x00, 0x99, 0x01, 0x04, date_1904 as u8, 0, 0, 0])
+        .unwrap();
+    zip.finish().unwrap();
+}
+
+/// .ods file whose content declares its null date
+pub fn write_ods_null_date(path: &Path, null_date: &str) {
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    zip.start_file("content.xml", Default::default()).unwrap();
+    write!(
+        zip,
+        "<table:calculation-settings><table:null-date table:date-value=\"{}\"/>\
+         </table:calculation-settings>",
+        null_date
+    )
+    .unwrap();
+    zip.finish().unwrap();
+}
+
+fn write_table(path: &Path, logiciel_type: LogicielType, rows: &[Row], date: &Format) {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.write(0, 0, "Export PLM").unwrap();
+    sheet.write(2, 0, "Generated for the tests").unwrap();
+
+    let header_row = 5;
+    let reference_headers = logiciel_type.reference_headers();
+    for (column, header) in reference_headers.iter().chain(HEADERS.iter()).enumerate() {
+        sheet.write(header_row, column as u16, *header).unwrap();
+    }
+    for (index, row) in rows.iter().enumerate() {
+        let line = header_row + 1 + index as u32;
+        for (column, value) in [row.reference.0, row.reference.1].into_iter().enumerate() {
+            if let Ok(number) = value.parse::<f64>() {
+                sheet.write(line, column as u16, number).unwrap();
+            } else if !value.is_empty() {
+                sheet.write(line, column as u16, value).unwrap();
+            }
+        }
+        sheet.write(line, 2, "SD CT 2").unwrap();
+        if !row.based_on.is_empty() {
+            sheet.write(line, 3, row.based_on).unwrap();
+        }
+        for (column, serial) in [(4, row.created), (5, row.archived)] {
+            if let Some(serial) = serial {
+                sheet
+                    .write_number_with_format(line, column, serial, date)
+                    .unwrap();
+            }
+        }
+        sheet.write(line, 7, "S-42").unwrap();
+        sheet.write(line, 8, row.author).unwrap();
+        sheet.write(line, 9, row.comment).unwrap();
+    }
+    workbook.save(path).unwrap();
+}
+
+/// Zip holding `files` under a single `root` folder, as the archived sources do
+pub fn write_zip(path: &Path, root: &str, files: &[(&str, &str)]) {
+    write_zip_at(path, root, files, zip::DateTime::default());
+}
+
+/// Same as `write_zip`, every file modified at `modified`
+pub fn write_zip_at(path: &Path, root: &str, files: &[(&str, &str)], modified: zip::DateTime) {
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    let options = zip::write::FileOptions::default().last_modified_time(modified);
+    for (name, content) in files {
+        zip.start_file(format!("{}/{}", root, name), options)
+            .unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+/// Tar archive holding `files`, with their mode, under a single `root` folder, compressed as its
+/// extension says
+pub fn write_tar(path: &Path, root: &str, files: &[(&str, &str, u32)]) {
+    let file = File::create(path).unwrap();
+    let name = path.to_string_lossy();
+    let out: Box<dyn Write> = if name.ends_with(".tar.gz") {
+        Box::new(flate2::write::GzEncoder::new(
+            file,
+            flate2::Compression::default(),
+        ))
+    } else if name.ends_with(".tar.xz") {
+        Box::new(xz2::write::XzEncoder::new(file, 6))
+    } else if name.ends_with(".tar.bz2") {
+        Box::new(bzip2::write::BzEncoder::new(
+            file,
+            bzip2::Compression::default(),
+        ))
+    } else {
+        Box::new(file)
+    };
+    let mut tar = tar::Builder::new(out);
+    for (name, content, mode) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(*mode);
+        header.set_mtime(1_600_000_000);
+        tar.append_data(
+            &mut header,
+            format!("{}/{}", root, name),
+            content.as_bytes(),
+        )
+        .unwrap();
+    }
+    tar.into_inner().unwrap().flush().unwrap();
+}
+
+/// Folder of sources holding `files`, with their mode, under a single `root` folder
+pub fn write_folder(path: &Path, root: &str, files: &[(&str, &str, u32)]) {
+    use std::os::unix::fs::PermissionsExt;
+    for (name, content, mode) in files {
+        let file = path.join(root).join(name);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, content).unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(*mode)).unwrap();
+    }
+}
+
+/// Unix timestamp of an Excel serial number
+pub fn unix_seconds(serial: f64) -> i64 {
+    ((serial - 25569.0) * 86_400.0).round() as i64
+}
+
+/// Migration folder: `xls/`, `zips/`, `parquets/`, `temp/` and the repository `repo/`
+pub struct Migration {
+    pub dir: TempDir,
+    pub workbooks: Vec<(LogicielType, PathBuf)>,
+}
+
+impl Migration {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        for folder in ["xls", "zips", "parquets"] {
+            fs::create_dir_all(dir.path().join(folder)).unwrap();
+        }
+        Migration {
+            dir,
+            workbooks: Vec::new(),
+        }
+    }
+
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.dir.path().join(relative)
+    }
+
+    pub fn workbook(&mut self, file_name: &str, logiciel_type: LogicielType, rows: &[Row]) {
+        let path = self.path("xls").join(file_name);
+        write_workbook(&path, logiciel_type, rows);
+        self.workbooks.push((logiciel_type, path));
+    }
+
+    pub fn zip(&self, reference: &str, files: &[(&str, &str)]) {
+        let path = self.path("zips").join(format!("Sources {}.zip", reference));
+        write_zip(&path, &format!("project-{}", reference), files);
+    }
+
+    pub fn prepare_config(&self) -> PrepareConfig {
+        PrepareConfig {
+            workbooks: self
+                .workbooks
+                .iter()
+                .map(|(logiciel_type, path)| WorkbookConfig {
+                    path: path.clone(),
+                    logiciel_type: *logiciel_type,
+                    drop_columns: None,
+                    date_system: None,
+                })
+                .collect(),
+            output_dir: Some(self.path("parquets")),
+            ..PrepareConfig::default()
+        }
+    }
+
+    pub fn push_config(&self, base_references: &[&str]) -> PushConfig {
+        PushConfig {
+            repo_path: Some(self.path("repo")),
+            parquets_dir: Some(self.path("parquets")),
+            base_references: base_references.iter().map(|r| r.to_string()).collect(),
+            zip_folders: vec![self.path("zips")],
+            temp_dir: Some(self.path("temp")),
+            ..PushConfig::default()
+        }
+    }
+
+    pub fn prepare(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let settings = PrepareSettings::resolve(self.prepare_config(), PrepareConfig::default())?;
+        git_push_automatic::prepare::prepare(&settings)
+    }
+
+    pub fn push(&self, base_references: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+        self.push_with(self.push_config(base_references))
+    }
+
+    pub fn push_with(&self, overrides: PushConfig) -> Result<(), Box<dyn std::error::Error>> {
+        let settings = PushSettings::resolve(overrides, PushConfig::default())?;
+        git_push_automatic::push::push(&settings)
+    }
+
+    pub fn plan(&self, base_references: &[&str]) -> Plan {
+        self.plan_with(self.push_config(base_references))
+    }
+
+    pub fn plan_with(&self, overrides: PushConfig) -> Plan {
+        let settings = PushSettings::resolve(overrides, PushConfig::default()).unwrap();
+        git_push_automatic::plan::plan(&settings).unwrap()
+    }
+
+    pub fn repo(&self) -> git2::Repository {
+        git2::Repository::open(self.path("repo")).unwrap()
+    }
+}
+
+/// The SD CT software of the tests, in three exports:
+///
+/// ```text
+/// A - B - C - E - F         (code, F in the CT code export)
+///      \
+///       D - 12000345        (code, then etude)
+/// X999                      (CT code, alone)
+/// ```
+///
+/// Every version has a zip, except F and 12000345 which reuse the sources of their parent.
+pub fn sd_ct() -> Migration {
+    let mut migration = Migration::new();
+    migration.workbook(
+        "code.xlsx",
+        LogicielType::Code,
+        &[
+            row(("B13264R", "A"), "", 44000.5, "Jean Dupont", "initial"),
+            row(
+                ("B13264R", "B"),
+                "B13264R-A",
+                44010.25,
+                "Jean Dupont",
+                "fix",
+            ),
+            row(
+                ("B13264R", "C"),
+                "B13264R-B",
+                44020.0,
+                "Zoé Martin",
+                "feature",
+            ),
+            row(
+                ("B13264R", "D"),
+                "B13264R-B",
+                44030.0,
+                "Zoé Martin",
+                "branch",
+            ),
+            row(
+                ("B13264R", "E"),
+                "B13264R-C",
+                44040.0,
+                "Jean Dupont",
+                "after C",
+            ),
+        ],
+    );
+    migration.workbook(
+        "ct_code.xlsx",
+        LogicielType::Code,
+        &[
+            row(("B13264R", "F"), "B13264R-E", 44050.0, "Jean Dupont", "ct"),
+            row(("X999", ""), "", 44000.0, "Nobody", "alone"),
+        ],
+    );
+    migration.workbook(
+        "etude.xlsx",
+        LogicielType::Etude,
+        &[row(
+            ("12", "345"),
+            "B13264R-D",
+            44060.0,
+            "Zoé Martin",
+            "study",
+        )],
+    );
+    for (reference, version) in [
+        ("B13264R-A", "1"),
+        ("B13264R-B", "2"),
+        ("B13264R-C", "3"),
+        ("B13264R-D", "4"),
+        ("B13264R-E", "5"),
+        ("X999", "9"),
+    ] {
+        migration.zip(
+            reference,
+            &[
+                ("src/main.c", &format!("int version = {};\n", version)),
+                ("README", reference),
+            ],
+        );
+    }
+    migration
+}
@@ -0,0 +1,1868 @@
+//! prepare → push on synthetic exports, checked on the tables and on the git graph.
+
+mod common;
+
+use common::{
+    row, sd_ct, unix_seconds, write_folder, write_ods_null_date, write_tar, write_workbook,
+    write_workbook_1904, write_xls_date_mode, write_xlsb_date_mode, write_zip, write_zip_at,
+    xls_date_mode, Migration,
+};
+use git_push_automatic::archive::get_project_root;
+use git_push_automatic::config::{
+    MessageConfig, MigrationFile, PrepareConfig, PrepareSettings, PushConfig, PushSettings,
+    TableSettings,
+};
+use git_push_automatic::date_mode;
+use git_push_automatic::duplicates::{DuplicatePolicy, KEPT_EXCEL_ROW, KEPT_SOURCE};
+use git_push_automatic::export::{export_lineage, ExportFormat};
+use git_push_automatic::fast_import::FastImport;
+use git_push_automatic::git::GitInfo;
+use git_push_automatic::ingest::{DateSystem, LogicielType, EXCEL_ROW, SOURCE};
+use git_push_automatic::lineage::{
+    read_lineage_table, CyclePolicy, CHILDREN_COUNT, DEPTH, LINEAGE_ROOT, PARENT_REFERENCE,
+    REFERENCE,
+};
+use git_push_automatic::plan::{plan, DateSource, NoZipPolicy, PlanFormat, ZipSource};
+use git_push_automatic::push::{verify, Backend};
+use polars::prelude::{ParquetReader, SerReader};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+
+/// Commit tagged with `reference`
+fn tagged_commit<'r>(repo: &'r git2::Repository, reference: &str) -> git2::Commit<'r> {
+    repo.find_reference(&format!("refs/tags/{}", reference))
+        .unwrap_or_else(|_| panic!("{} is not tagged", reference))
+        .peel_to_commit()
+        .unwrap()
+}
+
+fn branch_head<'r>(repo: &'r git2::Repository, branch: &str) -> git2::Commit<'r> {
+    repo.find_branch(branch, git2::BranchType::Local)
+        .unwrap_or_else(|_| panic!("no branch {}", branch))
+        .get()
+        .peel_to_commit()
+        .unwrap()
+}
+
+fn file_content(repo: &git2::Repository, commit: &git2::Commit, path: &str) -> String {
+    let entry = commit
+        .tree()
+        .unwrap()
+        .get_path(std::path::Path::new(path))
+        .unwrap_or_else(|_| panic!("{} not in commit {}", path, commit.id()));
+    let blob = repo.find_blob(entry.id()).unwrap();
+    String::from_utf8(blob.content().to_vec()).unwrap()
+}
+
+#[test]
+fn prepare_writes_the_lineage_table() {
+    let migration = sd_ct();
+    migration.prepare().unwrap();
+
+    let df = read_lineage_table(&migration.path("parquets")).unwrap();
+    assert_eq!(df.height(), 8);
+    let references = df.column(REFERENCE).unwrap().str().unwrap();
+    let parents = df.column(PARENT_REFERENCE).unwrap().str().unwrap();
+    let roots = df.column(LINEAGE_ROOT).unwrap().str().unwrap();
+    let depths = df.column(DEPTH).unwrap().u32().unwrap();
+    let children = df.column(CHILDREN_COUNT).unwrap().u32().unwrap();
+    let by_reference: HashMap<&str, usize> = references
+        .into_iter()
+        .enumerate()
+        .map(|(row, reference)| (reference.unwrap(), row))
+        .collect();
+
+    let version = |reference: &str| {
+        let row = by_reference[reference];
+        (
+            parents.get(row),
+            roots.get(row),
+            depths.get(row),
+            children.get(row),
+        )
+    };
+    assert_eq!(
+        version("B13264R-A"),
+        (None, Some("B13264R-A"), Some(0), Some(1))
+    );
+    assert_eq!(
+        version("B13264R-B"),
+        (Some("B13264R-A"), Some("B13264R-A"), Some(1), Some(2))
+    );
+    assert_eq!(
+        version("B13264R-F"),
+        (Some("B13264R-E"), Some("B13264R-A"), Some(4), Some(0))
+    );
+    assert_eq!(
+        version("12000345"),
+        (Some("B13264R-D"), Some("B13264R-A"), Some(3), Some(0))
+    );
+    assert_eq!(version("X999"), (None, Some("X999"), Some(0), Some(0)));
+
+    let base_references =
+        std::fs::read_to_string(migration.path("parquets/base-references.csv")).unwrap();
+    assert_eq!(base_references.trim(), "B13264R-A");
+    let report = std::fs::read_to_string(migration.path("parquets/quality_report.txt")).unwrap();
+    assert!(report.contains("isolated_version: 1"), "{}", report);
+    assert!(report.contains("X999 (ct_code.xlsx row 8)"), "{}", report);
+}
+
+#[test]
+fn the_lineage_is_drawn_in_dot_and_mermaid() {
+    let migration = sd_ct();
+    migration.prepare().unwrap();
+    let df = read_lineage_table(&migration.path("parquets")).unwrap();
+    // Id and line of the node of `reference`, its label starts with the reference
+    let node = |graph: &str, reference: &str| {
+        let line = graph
+            .lines()
+            .find(|line| {
+                line.contains(&format!("label=\"{}\\n", reference))
+                    || line.contains(&format!("\"{}<br/>", reference))
+            })
+            .unwrap_or_else(|| panic!("no node {} in {}", reference, graph))
+            .trim()
+            .to_owned();
+        let id: String = line
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+        (id, line)
+    };
+    let edges = |graph: &str, arrow: &str| -> HashSet<(String, String)> {
+        graph
+            .lines()
+            .filter_map(|line| line.trim().trim_end_matches(';').split_once(arrow))
+            .map(|(parent, child)| (parent.to_owned(), child.to_owned()))
+            .collect()
+    };
+    let references = [
+        "B13264R-A",
+        "B13264R-B",
+        "B13264R-C",
+        "B13264R-D",
+        "12000345",
+        "X999",
+    ];
+
+    let dot = export_lineage(&df, &[], ExportFormat::Dot).unwrap();
+    let ids: HashMap<&str, String> = references
+        .iter()
+        .map(|reference| (*reference, node(&dot, reference).0))
+        .collect();
+    let dot_edges = edges(&dot, " -> ");
+    assert_eq!(dot_edges.len(), 6, "{}", dot);
+    for (parent, child) in [
+        ("B13264R-A", "B13264R-B"),
+        ("B13264R-B", "B13264R-C"),
+        ("B13264R-B", "B13264R-D"),
+        ("B13264R-D", "12000345"),
+    ] {
+        assert!(
+            dot_edges.contains(&(ids[parent].clone(), ids[child].clone())),
+            "{}",
+            dot
+        );
+    }
+    assert!(dot_edges
+        .iter()
+        .all(|(parent, child)| *parent != ids["X999"] && *child != ids["X999"]));
+    assert!(node(&dot, "B13264R-A").1.contains("shape=box"));
+    assert!(node(&dot, "12000345").1.contains("shape=ellipse"));
+
+    let mermaid = export_lineage(&df, &[], ExportFormat::Mermaid).unwrap();
+    assert!(mermaid.starts_with("flowchart LR\n"));
+    let ids: HashMap<&str, String> = references
+        .iter()
+        .map(|reference| (*reference, node(&mermaid, reference).0))
+        .collect();
+    let mermaid_edges = edges(&mermaid, " --> ");
+    assert_eq!(mermaid_edges.len(), 6, "{}", mermaid);
+    assert!(mermaid_edges.contains(&(ids["B13264R-B"].clone(), ids["B13264R-D"].clone())));
+    assert!(mermaid_edges
+        .iter()
+        .all(|(parent, child)| *parent != ids["X999"] && *child != ids["X999"]));
+    assert!(node(&mermaid, "B13264R-A").1.ends_with("\"]:::code"));
+    let (id, line) = node(&mermaid, "12000345");
+    assert!(
+        line.starts_with(&format!("{}([\"", id)) && line.ends_with("\"]):::etude"),
+        "{}",
+        line
+    );
+
+    // One base reference: the other lineage is left out
+    let alone = export_lineage(&df, &["X999".to_owned()], ExportFormat::Dot).unwrap();
+    node(&alone, "X999");
+    assert!(!alone.contains("B13264R"), "{}", alone);
+    assert!(edges(&alone, " -> ").is_empty(), "{}", alone);
+    let error = export_lineage(&df, &["B13264R-B".to_owned()], ExportFormat::Mermaid)
+        .expect_err("no error")
+        .to_string();
+    assert!(error.contains("B13264R-B is not the root"), "{}", error);
+}
+
+#[test]
+fn push_rebuilds_the_git_graph() {
+    let migration = sd_ct();
+    migration.prepare().unwrap();
+    migration.push(&["B13264R-A"]).unwrap();
+    let repo = migration.repo();
+
+    // Parents: one commit per version on top of the commit of the version it is based on
+    let first_init = tagged_commit(&repo, "B13264R-A").parent(0).unwrap();
+    assert_eq!(first_init.message(), Some("First init"));
+    assert_eq!(first_init.parent_count(), 0);
+    for (reference, parent) in [
+        ("B13264R-B", "B13264R-A"),
+        ("B13264R-C", "B13264R-B"),
+        ("B13264R-D", "B13264R-B"),
+        ("B13264R-E", "B13264R-C"),
+        ("B13264R-F", "B13264R-E"),
+        ("12000345", "B13264R-D"),
+    ] {
+        let commit = tagged_commit(&repo, reference);
+        assert_eq!(commit.parent_count(), 1, "{}", reference);
+        assert_eq!(
+            commit.parent_id(0).unwrap(),
+            tagged_commit(&repo, parent).id(),
+            "parent of {}",
+            reference
+        );
+    }
+
+    // Branches: the oldest child continues the branch, the others start their own
+    assert_eq!(
+        branch_head(&repo, "B13264R-A").id(),
+        tagged_commit(&repo, "B13264R-F").id()
+    );
+    assert_eq!(
+        branch_head(&repo, "B13264R-D").id(),
+        tagged_commit(&repo, "12000345").id()
+    );
+    assert!(repo.find_branch("X999", git2::BranchType::Local).is_err());
+
+    // Messages, authors and dates come from the exports
+    let b = tagged_commit(&repo, "B13264R-B");
+    assert_eq!(b.message(), Some("[B13264R-B] fix"));
+    assert_eq!(b.author().name(), Some("Jean Dupont"));
+    assert_eq!(b.author().email(), Some("jeandupont@allianz.com"));
+    assert_eq!(b.author().when().seconds(), unix_seconds(44010.25));
+    assert_eq!(b.committer().when().seconds(), unix_seconds(44010.25));
+    let etude = tagged_commit(&repo, "12000345");
+    assert_eq!(etude.author().name(), Some("Zoé Martin"));
+    assert_eq!(etude.author().when().seconds(), unix_seconds(44060.0));
+
+    // Trees: the project root of the zip, or the sources of the parent without a zip
+    for (reference, content) in [
+        ("B13264R-A", "int version = 1;\n"),
+        ("B13264R-D", "int version = 4;\n"),
+        ("B13264R-E", "int version = 5;\n"),
+        ("B13264R-F", "int version = 5;\n"),
+        ("12000345", "int version = 4;\n"),
+    ] {
+        let commit = tagged_commit(&repo, reference);
+        assert_eq!(
+            file_content(&repo, &commit, "src/main.c"),
+            content,
+            "{}",
+            reference
+        );
+    }
+    let tree = tagged_commit(&repo, "B13264R-C").tree().unwrap();
+    let mut names: Vec<String> = tree
+        .iter()
+        .map(|entry| entry.name().unwrap().to_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["README", "src"]);
+}
+
+#[test]
+fn each_base_reference_gets_an_unrelated_root() {
+    let migration = sd_ct();
+    migration.prepare().unwrap();
+    migration.push(&["B13264R-A", "X999"]).unwrap();
+    let repo = migration.repo();
+
+    let x999 = tagged_commit(&repo, "X999");
+    assert_eq!(branch_head(&repo, "X999").id(), x999.id());
+    let root = x999.parent(0).unwrap();
+    assert_eq!(root.parent_count(), 0);
+    assert_ne!(
+        root.id(),
+        tagged_commit(&repo, "B13264R-A").parent_id(0).unwrap()
+    );
+    assert_eq!(
+        file_content(&repo, &x999, "src/main.c"),
+        "int version = 9;\n"
+    );
+    assert!(repo
+        .merge_base(x999.id(), tagged_commit(&repo, "B13264R-F").id())
+        .is_err());
+}
+
+#[test]
+fn the_plan_matches_the_push() {
+    let migration = sd_ct();
+    migration.prepare().unwrap();
+    let plan = migration.plan(&["B13264R-A"]);
+    assert!(!migration.path("repo").exists());
+    assert!(plan.rebuild);
+
+    let commits = &plan.lineages[0].commits;
+    assert_eq!(commits.len(), 7);
+    let planned = |reference: &str| {
+        let commit = commits
+            .iter()
+            .find(|commit| commit.reference == reference)
+            .unwrap_or_else(|| panic!("{} is not planned", reference));
+        (
+            commit.parent.as_deref(),
+            commit.branch.as_str(),
+            commit.new_branch,
+            commit.zip_source,
+        )
+    };
+    assert_eq!(
+        planned("B13264R-A"),
+        (None, "B13264R-A", true, ZipSource::Own)
+    );
+    assert_eq!(
+        planned("B13264R-F"),
+        (Some("B13264R-E"), "B13264R-A", false, ZipSource::Previous)
+    );
+    assert_eq!(
+        planned("B13264R-D"),
+        (Some("B13264R-B"), "B13264R-D", true, ZipSource::Own)
+    );
+    assert_eq!(
+        planned("12000345"),
+        (Some("B13264R-D"), "B13264R-D", false, ZipSource::Previous)
+    );
+    let b = commits
+        .iter()
+        .find(|commit| commit.reference == "B13264R-B")
+        .unwrap();
+    assert_eq!(b.author_email, "jeandupont@allianz.com");
+    assert_eq!(b.commit_time, Some(unix_seconds(44010.25)));
+    assert_eq!(b.message, "[B13264R-B] fix");
+
+    let json: serde_json::Value =
+        serde_json::from_str(&plan.render(PlanFormat::Json).unwrap()).unwrap();
+    assert_eq!(json["lineages"][0]["commits"].as_array().unwrap().len(), 7);
+    let text = plan.render(PlanFormat::Text).unwrap();
+    assert!(
+        text.contains("Branches to create: B13264R-A, B13264R-D"),
+        "{}",
+        text
+    );
+
+    migration.push(&["B13264R-A"]).unwrap();
+    let repo = migration.repo();
+    let branches: HashSet<String> = repo
+        .branches(Some(git2::BranchType::Local))
+        .unwrap()
+        .map(|branch| branch.unwrap().0.name().unwrap().unwrap().to_owned())
+        // The unborn default branch only receives the first "First init" commit
+        .filter(|branch| branch != "master")
+        .collect();
+    let planned_branches: HashSet<String> =
+        commits.iter().map(|commit| commit.branch.clone()).collect();
+    assert_eq!(branches, planned_branches);
+    // The last commit planned on a branch is its head
+    for commit in commits {
+        let last_on_branch = commits
+            .iter()
+            .rev()
+            .find(|other| other.branch == commit.branch)
+            .unwrap();
+        assert_eq!(
+            branch_head(&repo, &commit.branch).id(),
+            tagged_commit(&repo, &last_on_branch.reference).id()
+        );
+    }
+}
+
+#[test]
+fn authors_are_mapped_to_one_identity() {
+    let mut migration = Migration::new();
+    migration.workbook(
+        "code.xlsx",
+        LogicielType::Code,
+        &[
+            row(("B13264R", "A"), "", 44000.0, "J. Dupont", "initial"),
+            row(
+                ("B13264R", "B"),
+                "B13264R-A",
+                44010.0,
+                "JEAN  DUPONT",
+                "fix",
+            ),
+            row(
+                ("B13264R", "C"),
+                "B13264R-B",
+                44020.0,
+                "Zoé Martin",
+                "feature",
+            ),
+        ],
+    );
+    migration.zip("B13264R-A", &[("README", "A"), ("src/main.c", "")]);
+    std::fs::write(
+        migration.path("authors.mailmap"),
+        "# Team\nJean Dupont <jean.dupont@corp.example> J. Dupont\n",
+    )
+    .unwrap();
+    migration.prepare().unwrap();
+    let config = || PushConfig {
+        authors: Some(migration.path("authors.mailmap")),
+        author_email_domain: Some("corp.example".to_owned()),
+        ..migration.push_config(&["B13264R-A"])
+    };
+
+    let plan = migration.plan_with(config());
+    let identities: Vec<(&str, &str)> = plan.lineages[0]
+        .commits
+        .iter()
+        .map(|commit| (commit.author_name.as_str(), commit.author_email.as_str()))
+        .collect();
+    assert_eq!(
+        identities,
+        [
+            ("Jean Dupont", "jean.dupont@corp.example"),
+            ("Jean Dupont", "jean.dupont@corp.example"),
+            ("Zoé Martin", "zoemartin@corp.example"),
+        ]
+    );
+    let unmapped: Vec<(&str, usize)> = plan
+        .unmapped_authors
+        .iter()
+        .map(|author| (author.name.as_str(), author.versions))
+        .collect();
+    assert_eq!(unmapped, [("Zoé Martin", 1)]);
+
+    migration.push_with(config()).unwrap();
+    let repo = migration.repo();
+    let b = tagged_commit(&repo, "B13264R-B");
+    assert_eq!(b.author().name(), Some("Jean Dupont"));
+    assert_eq!(b.author().email(), Some("jean.dupont@corp.example"));
+}
+
+#[test]
+fn a_version_without_a_reference_is_nobody_s_parent() {
+    let mut migration = Migration::new();
+    migration.workbook(
+        "code.xlsx",
+        LogicielType::Code,
+        &[
+            row(("B13264R", "A"), "", 44000.0, "Jean Dupont", "initial"),
+            row(("B13264R", "B"), "B13264R-A", 44010.0, "Jean Dupont", "fix"),
+            // Blank Software P/N: no reference, like the Based On of the roots
+            row(("", ""), "", 44005.0, "Jean Dupont", "blank"),
+            // Neither is a blank version based on another one its child
+            row(("", ""), "B13264R-A", 44006.0, "Jean Dupont", "blank child"),
+        ],
+    );
+    migration.prepare().unwrap();
+
+    let df = read_lineage_table(&migration.path("parquets")).unwrap();
+    let references = df.column(REFERENCE).unwrap().str().unwrap();
+    let roots = df.column(LINEAGE_ROOT).unwrap().str().unwrap();
+    let root_of_a = references
+        .into_iter()
+        .position(|reference| reference == Some("B13264R-A"))
+        .map(|row| roots.get(row));
+    assert_eq!(root_of_a, Some(Some("B13264R-A")));
+    migration.zip("B13264R-A", &[("README", "A")]);
+    migration.zip("B13264R-B", &[("README", "B")]);
+    let planned = migration.plan(&["B13264R-A"]);
+    assert_eq!(planned.lineages[0].commits.len(), 2);
+    migration.push(&["B13264R-A"]).unwrap();
+    assert_eq!(
+        tagged_commit(&migration.repo(), "B13264R-B")
+            .parent(0)
+            .unwrap()
+            .id(),
+        tagged_commit(&migration.repo(), "B13264R-A").id()
+    );
+
+    let summary = std::fs::read_to_string(migration.path("parquets/quality_report.txt")).unwrap();
+    assert!(summary.contains("missing_reference: 2"), "{}", summary);
+}
+
+#[test]
+fn duplicated_references_follow_the_duplicate_policy() {
+    let mut migration = Migration::new();
+    migration.workbook(
+        "code.xlsx",
+        LogicielType::Code,
+        &[
+            row(("B13264R", "A"), "", 44000.0, "Jean Dupont", "initial"),
+            row(
+                ("B13264R", "B"),
+                "B13264R-A",
+                44010.0,
+                "Jean Dupont",
+                "code B",
+            ),
+        ],
+    );
+    // The same version exported again by the CT tool, created later
+    migration.workbook(
+        "ct_code.xlsx",
+        LogicielType::Code,
+        &[row(
+            ("B13264R", "B"),
+            "B13264R-A",
+            44020.0,
+            "Zoé Martin",
+            "ct B",
+        )],
+    );
+    let prepare = |on_duplicate: DuplicatePolicy, preferred_source: Option<&str>| {
+        let settings = PrepareSettings::resolve(
+            PrepareConfig {
+                on_duplicate: Some(on_duplicate),
+                preferred_source: preferred_source.map(str::to_owned),
+                ..migration.prepare_config()
+            },
+            PrepareConfig::default(),
+        )?;
+        git_push_automatic::prepare::prepare(&settings)
+    };
+    // Comment of B in the lineage table, and source, Excel row, kept source and kept Excel row
+    // of the row left out
+    let outcome = || {
+        let parquets = migration.path("parquets");
+        let df = read_lineage_table(&parquets).unwrap();
+        let references = df.column(REFERENCE).unwrap().str().unwrap();
+        let row = references
+            .into_iter()
+            .position(|reference| reference == Some("B13264R-B"))
+            .unwrap();
+        let comment = df.column("Comments").unwrap().str().unwrap().get(row);
+        let audit = ParquetReader::new(File::open(parquets.join("duplicates.parquet")).unwrap())
+            .finish()
+            .unwrap();
+        assert_eq!(audit.height(), 1);
+        let text = |column: &str| audit.column(column).unwrap().str().unwrap().get(0).unwrap();
+        let number = |column: &str| audit.column(column).unwrap().u32().unwrap().get(0).unwrap();
+        let left_out = format!(
+            "{} {} row {}, kept {} row {}",
+            text("Reference"),
+            text(SOURCE),
+            number(EXCEL_ROW),
+            text(KEPT_SOURCE),
+            number(KEPT_EXCEL_ROW)
+        );
+        (comment.unwrap().to_owned(), left_out)
+    };
+
+    prepare(DuplicatePolicy::Latest, None).unwrap();
+    assert_eq!(
+        outcome(),
+        (
+            "ct B".to_owned(),
+            "B13264R-B code.xlsx row 8, kept ct_code.xlsx row 7".to_owned()
+        )
+    );
+
+    prepare(DuplicatePolicy::PreferSource, Some("code.xlsx")).unwrap();
+    assert_eq!(
+        outcome(),
+        (
+            "code B".to_owned(),
+            "B13264R-B ct_code.xlsx row 7, kept code.xlsx row 8".to_owned()
+        )
+    );
+
+    let error = prepare(DuplicatePolicy::Fail, None)
+        .expect_err("no error")
+        .to_string();
+    assert!(
+        error.contains("1 duplicated reference(s)") && error.contains("B13264R-B"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn the_migration_file_is_checked_up_front() {
+    let example = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("migration.example.toml");
+    let file = MigrationFile::load(&example).unwrap();
+    assert_eq!(file.push.base_references, vec!["B13264R-A".to_owned()]);
+
+    let migration = Migration::new();
+    let path = migration.path("migration.toml");
+    std::fs::write(&path, "[pushh]\nrepo_path = \"../legacy-to-git\"\n").unwrap();
+    let error = MigrationFile::load(&path)
+        .err()
+        .expect("no error")
+        .to_string();
+    assert!(error.contains("unknown field `pushh`"), "{}", error);
+}
+
+#[test]
+fn messages_follow_the_templates() {
+    let mut migration = Migration::new();
+    migration.workbook(
+        "code.xlsx",
+        LogicielType::Code,
+        &[
+            row(
+                ("B13264R", "A"),
+                "",
+                44000.5,
+                "Jean Dupont",
+                "Port of the legacy scheduler to the new board\nsecond line",
+            ),
+            row(
+                ("B13264R", "B"),
+                "B13264R-A",
+                44100.5,
+                "Jean Dupont",
+                "Drivers of the second serial line and of the watchdog, with the timings measured \
+                 on the test bench",
+            ),
+        ],
+    );
+    migration.zip("B13264R-A", &[("src/main.c", "int version = 1;\n")]);
+    migration.zip("B13264R-B", &[("src/main.c", "int version = 2;\n")]);
+    migration.prepare().unwrap();
+    let config = |subject: &str| PushConfig {
+        message: MessageConfig {
+            subject: Some(subject.to_owned()),
+            body: Some("Study {Study Number}, archived on {Archive Date}".to_owned()),
+            trailers: vec![
+                "Study-Number: {Study Number}".to_owned(),
+                "Expedition-Date: {Expedition Date}".to_owned(),
+            ],
+            subject_width: Some(40),
+            body_width: Some(30),
+        },
+        ..migration.push_config(&["B13264R-A"])
+    };
+
+    let planned = migration.plan_with(config("[{reference}] {Comments}"));
+    // The subject is cut at 40 characters, what is cut off starts the body wrapped at 30, the
+    // empty trailer left out
+    assert_eq!(
+        planned.lineages[0].commits[0].message,
+        "[B13264R-A] Port of the legacy schedule…\n\n\
+         …r to the new board\n\
+         second line\n\n\
+         Study S-42, archived on\n\
+         2020-06-19 12:00\n\n\
+         Study-Number: S-42"
+    );
+    // A long Comments value is not repeated in the body, from its prefix on
+    assert_eq!(
+        planned.lineages[0].commits[1].message,
+        "[B13264R-B] Drivers of the second seria…\n\n\
+         …l line and of the watchdog,\n\
+         with the timings measured on\n\
+         the test bench\n\n\
+         Study S-42, archived on\n\
+         2020-09-27 12:00\n\n\
+         Study-Number: S-42"
+    );
+
+    let settings = PushSettings::resolve(config("{Comment}"), PushConfig::default()).unwrap();
+    let error = plan(&settings).err().expect("no error").to_string();
+    assert!(
+        error.starts_with("Unknown column(s) in the commit message template: Comment"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn serial_dates_are_exact_to_the_second() {
+    let system = DateSystem::Excel1900;
+    let millis = |year, month, day, hour, minute, second| {
+        chrono::NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, second)
+            .unwrap()
+            .and_utc()
+            .timestamp_millis()
+    };
+    assert_eq!(system.serial_to_millis(1.0), millis(1900, 1, 1, 0, 0, 0));
+    assert_eq!(system.serial_to_millis(59.5), millis(1900, 2, 28, 12, 0, 0));
+    assert_eq!(system.serial_to_millis(61.0), millis(1900, 3, 1, 0, 0, 0));
+    // 16:35:27 is 0.691284722... of a day
+    assert_eq!(
+        system.serial_to_millis(44000.0 + (16.0 * 3600.0 + 35.0 * 60.0 + 27.0) / 86400.0),
+        millis(2020, 6, 18, 16, 35, 27)
+    );
+    assert_eq!(
+        DateSystem::Excel1904.serial_to_millis(0.25),
+        millis(1904, 1, 1, 6, 0, 0)
+    );
+}
+
+#[test]
+fn the_date_system_is_read_from_every_format() {
+    let mut migration = Migration::new();
+    let xls = migration.path("xls");
+    for (date_1904, date_system) in [
+        (false, DateSystem::Excel1900),
+        (true, DateSystem::Excel1904),
+    ] {
+        let path = xls.join(format!("{}.xls", date_1904));
+        write_xls_date_mode(&path, date_1904);
+        assert_eq!(DateSystem::detect(&path).unwrap(), date_system);
+        let path = xls.join(format!("{}.xlsb", date_1904));
+        write_xlsb_date_mode(&path, date_1904);
+        assert_eq!(DateSystem::detect(&path).unwrap(), date_system);
+        let path = xls.join(format!("{}.xlsx", date_1904));
+        match date_1904 {
+            false => write_workbook(&path, LogicielType::Code, &[]),
+            true => write_workbook_1904(&path, LogicielType::Code, &[]),
+        }
+        assert_eq!(DateSystem::detect(&path).unwrap(), date_system);
+    }
+    for (null_date, date_system) in [
+        ("1899-12-30", DateSystem::Excel1900),
+        ("1904-01-01", DateSystem::Excel1904),
+    ] {
+        let path = xls.join(format!("{}.ods", null_date));
+        write_ods_null_date(&path, null_date);
+        assert_eq!(DateSystem::detect(&path).unwrap(), date_system);
+    }
+
+    // The date system is read once, when the settings are checked
+    let mut mac = Migration::new();
+    let path = mac.path("xls").join("mac.xls");
+    write_xls_date_mode(&path, true);
+    mac.workbooks.push((LogicielType::Code, path));
+    let settings =
+        PrepareSettings::resolve(mac.prepare_config(), PrepareConfig::default()).unwrap();
+    assert_eq!(
+        settings.workbooks[0].date_system,
+        Some(DateSystem::Excel1904)
+    );
+
+    // A workbook that cannot be read for its date system is refused, unless it is given
+    let broken = xls.join("broken.xls");
+    std::fs::write(&broken, "not a workbook").unwrap();
+    DateSystem::detect(&broken).expect_err("no error");
+    migration.workbooks.push((LogicielType::Code, broken));
+    let error = PrepareSettings::resolve(migration.prepare_config(), PrepareConfig::default())
+        .err()
+        .expect("no error")
+        .to_string();
+    assert!(
+        error.contains("cannot read the date system of workbook"),
+        "{}",
+        error
+    );
+    let mut config = migration.prepare_config();
+    config.workbooks[0].date_system = Some(DateSystem::Excel1904);
+    let settings = PrepareSettings::resolve(config, PrepareConfig::default()).unwrap();
+    assert_eq!(
+        settings.workbooks[0].date_system().unwrap(),
+        DateSystem::Excel1904
+    );
+}
+
+#[test]
+fn the_date_mode_of_a_truncated_or_malformed_workbook_is_unknown() {
+    for small in [false, true] {
+        for date_1904 in [false, true] {
+            let bytes = xls_date_mode(date_1904, small);
+            assert_eq!(date_mode::xls_declares_1904(&bytes), Some(date_1904));
+        }
+        // Every sector is needed to reach the DATEMODE record
+        let bytes = xls_date_mode(true, small);
+        for length in (0..bytes.len()).step_by(61) {
+            assert_eq!(
+                date_mode::xls_declares_1904(&bytes[..length]),
+                None,
+                "{}",
+                length
+            );
+        }
+    }
+
+    let bytes = xls_date_mode(true, false);
+    let corrupt = |offset: usize, patch: &[u8]| {
+        let mut bytes = bytes.clone();
+        bytes[offset..offset + patch.len()].copy_from_slice(patch);
+        date_mode::xls_declares_1904(&bytes)
+    };
+    // Signature, sector shift, count of DIFAT sectors, FAT chain looping on itself, no Workbook
+    // stream in the directory, and a BOF record running past the end of the stream
+    assert_eq!(corrupt(0, b"PK"), None);
+    assert_eq!(corrupt(0x1E, &[40, 0]), None);
+    assert_eq!(corrupt(0x48, &[0xFF; 4]), None);
+    assert_eq!(corrupt(512 + 4 * 9, &2u32.to_le_bytes()), None);
+    assert_eq!(corrupt(1024 + 128, &[0; 64]), None);
+    assert_eq!(corrupt(1536 + 2, &[0xFF, 0xFF]), None);
+    assert_eq!(date_mode::xls_declares_1904(b"not a workbook"), None);
+
+    for date_1904 in [false, true] {
+        let records = [0x83, 0x01, 0x00, 0x99, 0x01, 0x04, date_1904 as u8, 0, 0, 0];
+        assert_eq!(date_mode::xlsb_declares_1904(&records), Some(date_1904));
+        // Cut inside a record
+        for length in [1, 2, 4, 5, 6, 7, 8, 9] {
+            assert_eq!(
+                date_mode::xlsb_declares_1904(&records[..length]),
+                None,
+                "{}",
+                length
+            );
+        }
+    }
+    // A size longer than the records, a BrtWbProp record without data
+    assert_eq!(
+        date_mode::xlsb_declares_1904(&[0x99, 0x01, 0xFF, 0xFF, 0xFF, 0x7F]),
+        None
+    );
+    assert_eq!(date_mode::xlsb_declares_1904(&[0x99, 0x01, 0x00]), None);
+    // No BrtWbProp record: the 1900 system
+    assert_eq!(
+        date_mode::xlsb_declares_1904(&[0x83, 0x01, 0x00]),
+        Some(false)
+    );
+
+    assert!(date_mode::xlsx_declares_1904(
+        b"<workbookPr date1904=\"true\"/>"
+    ));
+    assert!(!date_mode::xlsx_declares_1904(
+        b"<workbookPr date1904=\"0\"/>"
+    ));
+    assert!(!date_mode::xlsx_declares_1904(&[0xFF, 0xFE, 0x00]));
+    assert!(!date_mode::ods_declares_1904(&[0xFF, 0xFE, 0x00]));
+}
+
+#[test]
+fn commits_get_the_offset_of_the_time_zone() {
+    let mut migration = Migration::new();
+    let path = migration.path("xls").join("mac.xlsx");
+    // 44000.5 and 44200.25 in the 1900 system: 2020-06-18 12:00 and 2021-01-04 06:00
+    write_workbook_1904(
+        &path,
+        LogicielType::Code,
+        &[
+            row(("B13264R", "A"), "", 42538.5, "Jean Dupont", "summer"),
+            row(
+                ("B13264R", "B"),
+                "B13264R-A",
+                42738.25,
+                "Jean Dupont",
+                "winter",
+            ),
+        ],
+    );
+    migration.workbooks.push((LogicielType::Code, path));
+    migration.zip("B13264R-A", &[("README", "A"), ("src/main.c", "")]);
+    migration.prepare().unwrap();
+
+    migration
+        .push_with(PushConfig {
+            time_zone: Some("Europe/Paris".to_owned()),
+            ..migration.push_config(&["B13264R-A"])
+        })
+        .unwrap();
+    let repo = migration.repo();
+    let summer = tagged_commit(&repo, "B13264R-A").author().when();
+    assert_eq!(
+        (summer.seconds(), summer.offset_minutes()),
+        (unix_seconds(44000.5) - 2 * 3600, 120)
+    );
+    let winter = tagged_commit(&repo, "B13264R-B").author().when();
+    assert_eq!(
+        (winter.seconds(), winter.offset_minutes()),
+        (unix_seconds(44200.25) - 3600, 60)
+    );
+
+    let overrides = PushConfig {
+        time_zone: Some("Paris".to_owned()),
+        ..migration.push_config(&["B13264R-A"])
+    };
+    let error = PushSettings::resolve(overrides, PushConfig::default())
+        .err()
+        .expect("no error")
+        .to_string();
+    assert!(error.contains("time_zone `Paris`"), "{}", error);
+}
+
+#[test]
+fn missing_dates_fall_back_along_the_chain() {
+    let mut migration = Migration::new();
+    migration.workbook(
+        "code.xlsx",
+        LogicielType::Code,
+        &[
+            row(("B13264R", "A"), "", 44000.5, "Jean Dupont", "dated"),
+            row(
+                ("B13264R", "B"),
+                "B13264R-A",
+                0.0,
+                "Jean Dupont",
+                "archived",
+            )
+            .dates(None, Some(44011.0)),
+            row(("B13264R", "C"), "B13264R-B", 0.0, "Jean Dupont", "zipped").dates(None, None),
+            row(("B13264R", "D"), "B13264R-C", 0.0, "Jean Dupont", "undated").dates(None, None),
+        ],
+    );
+    migration.zip("B13264R-A", &[("README", "A"), ("src/main.c", "")]);
+    write_zip_at(
+        &migration.path("zips").join("Sources B13264R-C.zip"),
+        "project-C",
+        &[("README", "C"), ("src/main.c", "")],
+        zip::DateTime::from_date_and_time(2020, 7, 1, 10, 30, 0).unwrap(),
+    );
+    migration.prepare().unwrap();
+
+    let planned = migration.plan(&["B13264R-A"]);
+    let dated: Vec<(&str, Option<i64>, Option<DateSource>)> = planned.lineages[0]
+        .commits
+        .iter()
+        .map(|commit| {
+            (
+                commit.reference.as_str(),
+                commit.commit_time,
+                commit.date_source,
+            )
+        })
+        .collect();
+    let zip_time = chrono::NaiveDate::from_ymd_opt(2020, 7, 1)
+        .unwrap()
+        .and_hms_opt(10, 30, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp();
+    assert_eq!(
+        dated,
+        [
+            (
+                "B13264R-A",
+                Some(unix_seconds(44000.5)),
+                Some(DateSource::CreationDate)
+            ),
+            (
+                "B13264R-B",
+                Some(unix_seconds(44011.0)),
+                Some(DateSource::ArchiveDate)
+            ),
+            ("B13264R-C", Some(zip_time), Some(DateSource::ZipMtime)),
+            ("B13264R-D", Some(zip_time + 1), Some(DateSource::Parent)),
+        ]
+    );
+    let text = planned.render(PlanFormat::Text).unwrap();
+    assert!(
+        text.contains("Versions dated by a fallback:\n  B13264R-B  archive-date\n"),
+        "{}",
+        text
+    );
+
+    let overrides = PushConfig {
+        commit_date: vec![DateSource::ArchiveDate],
+        ..migration.push_config(&["B13264R-A"])
+    };
+    let settings = PushSettings::resolve(overrides, PushConfig::default()).unwrap();
+    let error = plan(&settings).err().expect("no error").to_string();
+    assert_eq!(
+        error,
+        "No commit date for 2 version(s) of B13264R-A, none of the sources archive-date knows it: \
+         B13264R-C, B13264R-D"
+    );
+}
+
+#[test]
+fn the_odb_backend_writes_the_same_history_without_a_checkout() {
+    let migration = sd_ct();
+    // C replaces the README by a Makefile and adds a nested file
+    migration.zip(
+        "B13264R-C",
+        &[
+            ("src/main.c", "int version = 3;\n"),
+            ("src/lib/util.h", "#define UTIL\n"),
+            ("Makefile", "all:\n"),
+        ],
+    );
+    migration.prepare().unwrap();
+    let bases = ["B13264R-A", "X999"];
+    migration.push(&bases).unwrap();
+    migration
+        .push_with(PushConfig {
+            repo_path: Some(migration.path("bare.git")),
+            backend: Some(Backend::Odb),
+            bare: Some(true),
+            ..migration.push_config(&bases)
+        })
+        .unwrap();
+    migration
+        .push_with(PushConfig {
+            repo_path: Some(migration.path("odb")),
+            backend: Some(Backend::Odb),
+            ..migration.push_config(&bases)
+        })
+        .unwrap();
+
+    let worktree = migration.repo();
+    let bare = git2::Repository::open(migration.path("bare.git")).unwrap();
+    assert!(bare.is_bare());
+    // Same trees, dates, authors and parents: the very same commits
+    for reference in [
+        "B13264R-A",
+        "B13264R-B",
+        "B13264R-C",
+        "B13264R-D",
+        "B13264R-E",
+        "B13264R-F",
+        "12000345",
+        "X999",
+    ] {
+        assert_eq!(
+            tagged_commit(&bare, reference).id(),
+            tagged_commit(&worktree, reference).id(),
+            "{}",
+            reference
+        );
+    }
+    let c = tagged_commit(&bare, "B13264R-C");
+    assert_eq!(file_content(&bare, &c, "src/lib/util.h"), "#define UTIL\n");
+    assert!(c.tree().unwrap().get_path("README".as_ref()).is_err());
+
+    // A repository with a working tree gets its last branch checked out once, at the end
+    let odb = git2::Repository::open(migration.path("odb")).unwrap();
+    assert!(odb.statuses(None).unwrap().is_empty());
+    assert_eq!(
+        odb.head().unwrap().peel_to_commit().unwrap().id(),
+        tagged_commit(&odb, "X999").id()
+    );
+}
+
+#[test]
+fn a_fast_import_stream_replays_the_same_history() {
+    let migration = sd_ct();
+    migration.prepare().unwrap();
+    let bases = ["B13264R-A", "X999"];
+    migration.push(&bases).unwrap();
+    // Piped into git fast-import
+    migration
+        .push_with(PushConfig {
+            repo_path: Some(migration.path("piped")),
+            backend: Some(Backend::FastImport),
+            ..migration.push_config(&bases)
+        })
+        .unwrap();
+    // Written to a file, the repository is left alone
+    let stream = migration.path("migration.fi");
+    migration
+        .push_with(PushConfig {
+            repo_path: Some(migration.path("replayed")),
+            backend: Some(Backend::FastImport),
+            fast_import_output: Some(stream.clone()),
+            ..migration.push_config(&bases)
+        })
+        .unwrap();
+    assert!(!migration.path("replayed").exists());
+    let content = std::fs::read_to_string(&stream).unwrap();
+    assert!(content.starts_with("feature done\n"));
+    assert!(content.contains("\ntag B13264R-C\n"), "{}", content);
+    assert!(content.ends_with("done\n"));
+
+    let replayed = git2::Repository::init_bare(migration.path("replayed")).unwrap();
+    let status = std::process::Command::new("git")
+        .args(["fast-import", "--quiet"])
+        .current_dir(migration.path("replayed"))
+        .stdin(std::fs::File::open(&stream).unwrap())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let worktree = migration.repo();
+    let piped = git2::Repository::open(migration.path("piped")).unwrap();
+    assert!(piped.statuses(None).unwrap().is_empty());
+    for reference in ["B13264R-A", "B13264R-C", "B13264R-F", "12000345", "X999"] {
+        let commit = tagged_commit(&worktree, reference);
+        assert_eq!(tagged_commit(&piped, reference).id(), commit.id());
+        assert_eq!(tagged_commit(&replayed, reference).id(), commit.id());
+        // Annotated tags, dated like their commit
+        let tag = replayed
+            .find_reference(&format!("refs/tags/{}", reference))
+            .unwrap()
+            .peel_to_tag()
+            .unwrap();
+        assert_eq!(tag.tagger().unwrap().when(), commit.author().when());
+    }
+    assert_eq!(
+        branch_head(&replayed, "B13264R-D").id(),
+        tagged_commit(&worktree, "12000345").id()
+    );
+}
+
+#[test]
+fn fast_import_signs_like_libgit2_and_refuses_broken_identities() {
+    let git_info = |name: &str, email: &str| {
+        GitInfo::new(
+            Some(0),
+            60,
+            "message".to_owned(),
+            name.to_owned(),
+            email.to_owned(),
+            "B13264R-A".to_owned(),
+        )
+    };
+    let mut stream = FastImport::new(Vec::new()).unwrap();
+    stream
+        .first_init(&git_info(" J. Dupont Jr. ", "jdupont@allianz.com."), "main")
+        .unwrap();
+    let content = String::from_utf8(stream.finish().unwrap()).unwrap();
+    let signature = git2::Signature::new(
+        " J. Dupont Jr. ",
+        "jdupont@allianz.com.",
+        &git2::Time::new(0, 60),
+    )
+    .unwrap();
+    assert_eq!(
+        (signature.name().unwrap(), signature.email().unwrap()),
+        ("J. Dupont Jr", "jdupont@allianz.com")
+    );
+    assert!(
+        content.contains("\nauthor J. Dupont Jr <jdupont@allianz.com> 0 +0100\n"),
+        "{}",
+        content
+    );
+
+    for (name, email) in [
+        ("Jean <Dupont>", "jdupont@allianz.com"),
+        ("Jean Dupont", "jdupont@allianz.com>\ncommitter x"),
+        ("Jean\nDupont", "jdupont@allianz.com"),
+        ("...", "jdupont@allianz.com"),
+    ] {
+        let mut stream = FastImport::new(Vec::new()).unwrap();
+        let error = stream
+            .first_init(&git_info(name, email), "main")
+            .expect_err("no error")
+            .to_string();
+        assert!(error.contains("of B13264R-A"), "{}", error);
+    }
+}
+
+#[test]
+fn versions_without_a_zip_follow_the_no_zip_policy() {
+    let migration = sd_ct();
+    migration.prepare().unwrap();
+    let bases = ["B13264R-A"];
+    let push = |repo: &str, backend: Backend, no_zip: NoZipPolicy| {
+        migration
+            .push_with(PushConfig {
+                repo_path: Some(migration.path(repo)),
+                backend: Some(backend),
+                no_zip: Some(no_zip),
+                ..migration.push_config(&bases)
+            })
+            .unwrap();
+        git2::Repository::open(migration.path(repo)).unwrap()
+    };
+
+    // F and 12000345 have no zip: a commit keeping the tree of their parent, marked as such
+    let repo = push("repo", Backend::Worktree, NoZipPolicy::EmptyCommit);
+    let (e, f) = (
+        tagged_commit(&repo, "B13264R-E"),
+        tagged_commit(&repo, "B13264R-F"),
+    );
+    assert_eq!(f.parent_id(0).unwrap(), e.id());
+    assert_eq!(f.tree_id(), e.tree_id());
+    assert_eq!(
+        f.message().unwrap(),
+        "[B13264R-F] ct\n\nSource-Archive: none"
+    );
+    assert_eq!(
+        tagged_commit(&repo, "12000345").tree_id(),
+        tagged_commit(&repo, "B13264R-D").tree_id()
+    );
+    assert_eq!(
+        tagged_commit(
+            &push("odb", Backend::Odb, NoZipPolicy::EmptyCommit),
+            "B13264R-F"
+        )
+        .id(),
+        f.id()
+    );
+
+    // No commit: the tag points to the commit of the parent and carries the trailer
+    let repo = push("tagged", Backend::FastImport, NoZipPolicy::TagParent);
+    let e = tagged_commit(&repo, "B13264R-E");
+    assert_eq!(tagged_commit(&repo, "B13264R-F").id(), e.id());
+    assert_eq!(branch_head(&repo, "B13264R-A").id(), e.id());
+    let tag = repo
+        .find_reference("refs/tags/B13264R-F")
+        .unwrap()
+        .peel_to_tag()
+        .unwrap();
+    assert!(tag.message().unwrap().ends_with("Source-Archive: none"));
+
+    // Neither commit nor tag, a mark that `verify` and incremental pushes know
+    let repo = push("skipped", Backend::Odb, NoZipPolicy::Skip);
+    assert!(repo.find_reference("refs/tags/B13264R-F").is_err());
+    assert!(repo.find_reference("refs/tags/12000345").is_err());
+    assert_eq!(
+        branch_head(&repo, "B13264R-D").id(),
+        tagged_commit(&repo, "B13264R-D").id()
+    );
+    assert_eq!(
+        repo.find_reference("refs/skipped/B13264R-F")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .id(),
+        tagged_commit(&repo, "B13264R-E").id()
+    );
+    let skipped = PushConfig {
+        repo_path: Some(migration.path("skipped")),
+        no_zip: Some(NoZipPolicy::Skip),
+        ..migration.push_config(&bases)
+    };
+    verify(&TableSettings::resolve(&skipped, &PushConfig::default()).unwrap()).unwrap();
+    let planned = migration.plan_with(PushConfig {
+        incremental: Some(true),
+        ..skipped
+    });
+    assert!(planned.lineages[0]
+        .commits
+        .iter()
+        .all(|commit| commit.already_imported));
+    let streamed = push("skipped-stream", Backend::FastImport, NoZipPolicy::Skip);
+    assert!(streamed.find_reference("refs/skipped/12000345").is_ok());
+
+    // A zip of its own holding the files of its parent: the policy applies, with its own trailer
+    migration.zip(
+        "B13264R-F",
+        &[
+            ("src/main.c", "int version = 5;\n"),
+            ("README", "B13264R-E"),
+        ],
+    );
+    let repo = push("unchanged", Backend::Worktree, NoZipPolicy::TagParent);
+    let e = tagged_commit(&repo, "B13264R-E");
+    assert_eq!(tagged_commit(&repo, "B13264R-F").id(), e.id());
+    let tag = repo
+        .find_reference("refs/tags/B13264R-F")
+        .unwrap()
+        .peel_to_tag()
+        .unwrap();
+    assert!(tag
+        .message()
+        .unwrap()
+        .ends_with("Source-Archive: unchanged"));
+
+    // A root committed with the default zip has no zip of its own either
+    std::fs::remove_file(migration.path("zips").join("Sources X999.zip")).unwrap();
+    let planned = migration.plan_with(PushConfig {
+        default_zip: Some(migration.path("zips").join("Sources B13264R-A.zip")),
+        ..migration.push_config(&["X999"])
+    });
+    let root = &planned.lineages[0].commits[0];
+    assert_eq!(root.zip_source, ZipSource::Default);
+    assert!(
+        root.message.ends_with("Source-Archive: none"),
+        "{}",
+        root.message
+    );
+    // Without its default zip either: every backend refuses the root before the first commit
+    for backend in [Backend::Worktree, Backend::Odb, Backend::FastImport] {
+        let error = migration
+            .push_with(PushConfig {
+                repo_path: Some(migration.path("no-default")),
+                backend: Some(backend),
+                ..migration.push_config(&["X999"])
+            })
+            .expect_err("no error")
+            .to_string();
+        assert!(
+            error.contains("X999 has no zip of its own and its default zip")
+                && error.contains("Sources X999.zip"),
+            "{}",
+            error
+        );
+    }
+    // A zip that cannot be read is an error, not a panic, with every backend
+    std::fs::write(migration.path("zips").join("Sources X999.zip"), "not a zip").unwrap();
+    for backend in [Backend::Worktree, Backend::Odb, Backend::FastImport] {
+        let error = migration
+            .push_with(PushConfig {
+                repo_path: Some(migration.path(&format!("corrupt-{:?}", backend))),
+                backend: Some(backend),
+                ..migration.push_config(&["X999"])
+            })
+            .expect_err("no error")
+            .to_string();
+        assert!(!error.is_empty());
+        // git fast-import is stopped and waited for, not left reading a stream cut short
+        if backend == Backend::FastImport {
+            assert!(error.contains("git fast-import is stopped: "), "{}", error);
+        }
+    }
+    let text = migration
+        .plan_with(PushConfig {
+            no_zip: Some(NoZipPolicy::Skip),
+            ..migration.push_config(&bases)
+        })
+        .render(PlanFormat::Text)
+        .unwrap();
+    assert!(
+        text.contains("B13264R-F  skipped, the versions based on it are based on B13264R-E"),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn zips_are_found_by_their_exact_reference() {
+    let migration = sd_ct();
+    migration.prepare().unwrap();
+    // Contains the reference of F, but is not F
+    migration.zip("B13264R-F1", &[("src/main.c", "int version = 61;\n")]);
+    let more = migration.path("more");
+    std::fs::create_dir_all(&more).unwrap();
+    write_zip(
+        &more.join("Sources B13264R-B.zip"),
+        "project",
+        &[("src/main.c", "int version = 22;\n")],
+    );
+    write_zip(
+        &more.join("sources 12000345.ZIP"),
+        "project",
+        &[("src/main.c", "int version = 8;\n")],
+    );
+    let bases = ["B13264R-A"];
+    let planned = migration.plan_with(PushConfig {
+        zip_folders: vec![migration.path("zips"), more.clone()],
+        ..migration.push_config(&bases)
+    });
+    let zip_of = |reference: &str| {
+        let commit = planned.lineages[0]
+            .commits
+            .iter()
+            .find(|commit| commit.reference == reference)
+            .unwrap();
+        (commit.zip.clone(), commit.zip_source)
+    };
+    assert_eq!(zip_of("B13264R-F").1, ZipSource::Previous);
+    // The first zip folder wins
+    assert_eq!(
+        zip_of("B13264R-B"),
+        (
+            migration.path("zips").join("Sources B13264R-B.zip"),
+            ZipSource::Own
+        )
+    );
+    assert_eq!(
+        zip_of("12000345"),
+        (more.join("sources 12000345.ZIP"), ZipSource::Own)
+    );
+
+    // A pattern of another naming, the other zips are left out
+    write_zip(
+        &more.join("B13264R-C_sources.zip"),
+        "project",
+        &[("src/main.c", "int version = 33;\n")],
+    );
+    let planned = migration.plan_with(PushConfig {
+        zip_folders: vec![more.clone()],
+        zip_name_pattern: Some(r"^(?P<reference>[^_]+)_sources\.zip$".to_owned()),
+        default_zip: Some(migration.path("zips").join("Sources B13264R-A.zip")),
+        ..migration.push_config(&bases)
+    });
+    let c = planned.lineages[0]
+        .commits
+        .iter()
+        .find(|commit| commit.reference == "B13264R-C")
+        .unwrap();
+    assert_eq!(c.zip, more.join("B13264R-C_sources.zip"));
+    assert_eq!(planned.unmatched_zips.len(), 2);
+
+    // Zips filed under a folder named after their base reference: the folder is searched
+    let filed = migration.path("filed/B13264R-A");
+    std::fs::create_dir_all(&filed).unwrap();
+    write_zip(
+        &filed.join("Sources B13264R-B.zip"),
+        "project",
+        &[("src/main.c", "int version = 2;\n")],
+    );
+    let planned = migration.plan_with(PushConfig {
+        zip_folders: vec![migration.path("filed")],
+        default_zip: Some(migration.path("zips").join("Sources B13264R-A.zip")),
+        ..migration.push_config(&bases)
+    });
+    let b = &planned.lineages[0].commits[1];
+    assert_eq!(
+        (b.reference.as_str(), &b.zip, b.zip_source),
+        (
+            "B13264R-B",
+            &filed.join("Sources B13264R-B.zip"),
+            ZipSource::Own
+        )
+    );
+
+    // Twice in the same folder
+    std::fs::create_dir_all(migration.path("zips/old")).unwrap();
+    migration.zip("X999", &[("README", "X999")]);
+    write_zip(
+        &migration.path("zips/old/Sources X999.zip"),
+        "project",
+        &[("README", "old")],
+    );
+    let settings =
+        PushSettings::resolve(migration.push_config(&bases), PushConfig::default()).unwrap();
+    let error = plan(&settings).err().expect("no error").to_string();
+    assert!(
+        error.starts_with("1 reference(s) match several sources of the same folder:\nX999: "),
+        "{}",
+        error
+    );
+    assert!(error.contains("old/Sources X999.zip"), "{}", error);
+}
+
+#[test]
+fn the_project_root_stops_at_the_first_file() {
+    let migration = Migration::new();
+    let unpacked = migration.path("unpacked");
+    std::fs::create_dir_all(unpacked.join("project/src")).unwrap();
+    std::fs::write(unpacked.join("project/README"), "readme").unwrap();
+    std::fs::write(unpacked.join("project/src/main.c"), "int version = 1;\n").unwrap();
+    // A lone sub-folder next to a file is part of the project, not its root
+    assert_eq!(
+        get_project_root(&unpacked).unwrap(),
+        unpacked.join("project")
+    );
+}
+
+#[test]
+fn tar_archives_and_folders_are_read_as_zips() {
+    let migration = sd_ct();
+    migration.prepare().unwrap();
+    let zips = migration.path("zips");
+    for reference in ["B13264R-B", "B13264R-C", "B13264R-D", "B13264R-E", "X999"] {
+        std::fs::remove_file(zips.join(format!("Sources {}.zip", reference))).unwrap();
+    }
+    let sources = |version: &str, reference: &str| {
+        vec![
+            ("src/main.c", format!("int version = {};\n", version)),
+            ("README", reference.to_owned()),
+            ("build.sh", "make\n".to_owned()),
+        ]
+    };
+    for (file_name, version, reference) in [
+        ("Sources B13264R-B.tar.gz", "2", "B13264R-B"),
+        ("Sources B13264R-C.tar.xz", "3", "B13264R-C"),
+        ("Sources B13264R-D.tar.bz2", "4", "B13264R-D"),
+    ] {
+        let files = sources(version, reference);
+        let files: Vec<(&str, &str, u32)> = files
+            .iter()
+            .map(|(name, content)| (*name, content.as_str(), 0o755))
+            .collect();
+        write_tar(&zips.join(file_name), "project", &files);
+    }
+    // A folder named after a version holds its sources, any other folder is searched
+    let files = sources("5", "B13264R-E");
+    let files: Vec<(&str, &str, u32)> = files
+        .iter()
+        .map(|(name, content)| (*name, content.as_str(), 0o644))
+        .collect();
+    write_folder(&zips.join("Sources B13264R-E"), "project-E", &files);
+    std::fs::create_dir_all(zips.join("old")).unwrap();
+    write_tar(
+        &zips.join("old/X999.tar"),
+        "project",
+        &[
+            ("src/main.c", "int version = 9;\n", 0o644),
+            ("README", "X999", 0o644),
+        ],
+    );
+
+    let bases = ["B13264R-A", "X999"];
+    let planned = migration.plan_with(migration.push_config(&bases));
+    assert_eq!(
+        planned.lineages[1].commits[0].zip,
+        zips.join("old/X999.tar")
+    );
+    migration.push(&bases).unwrap();
+    migration
+        .push_with(PushConfig {
+            repo_path: Some(migration.path("odb")),
+            backend: Some(Backend::Odb),
+            ..migration.push_config(&bases)
+        })
+        .unwrap();
+    migration
+        .push_with(PushConfig {
+            repo_path: Some(migration.path("piped")),
+            backend: Some(Backend::FastImport),
+            ..migration.push_config(&bases)
+        })
+        .unwrap();
+
+    let worktree = migration.repo();
+    let odb = git2::Repository::open(migration.path("odb")).unwrap();
+    let piped = git2::Repository::open(migration.path("piped")).unwrap();
+    for (reference, version) in [
+        ("B13264R-B", "2"),
+        ("B13264R-C", "3"),
+        ("B13264R-D", "4"),
+        ("B13264R-E", "5"),
+        ("X999", "9"),
+    ] {
+        let commit = tagged_commit(&worktree, reference);
+        assert_eq!(
+            file_content(&worktree, &commit, "src/main.c"),
+            format!("int version = {};\n", version)
+        );
+        // Same project root and modes whatever reads the sources
+        assert_eq!(
+            tagged_commit(&odb, reference).id(),
+            commit.id(),
+            "{}",
+            reference
+        );
+        assert_eq!(
+            tagged_commit(&piped, reference).id(),
+            commit.id(),
+            "{}",
+            reference
+        );
+    }
+    let mode_of = |reference: &str| {
+        let commit = tagged_commit(&worktree, reference);
+        let tree = commit.tree().unwrap();
+        tree.get_path("build.sh".as_ref()).unwrap().filemode()
+    };
+    assert_eq!(mode_of("B13264R-B"), 0o100755);
+    assert_eq!(mode_of("B13264R-E"), 0o100644);
+}
+
+#[test]
+fn links_give_the_same_tree_with_every_backend() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    let migration = sd_ct();
+    migration.prepare().unwrap();
+    let zips = migration.path("zips");
+    for reference in ["B13264R-B", "B13264R-C", "B13264R-D"] {
+        std::fs::remove_file(zips.join(format!("Sources {}.zip", reference))).unwrap();
+    }
+
+    // B: a tar with a symlink to a file, a symlink to a folder and a hard link
+    let mut tar = tar::Builder::new(File::create(zips.join("Sources B13264R-B.tar")).unwrap());
+    for (name, content, mode) in [
+        ("project/src/main.c", "int version = 2;\n", 0o644),
+        ("project/run.sh", "make\n", 0o755),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(mode);
+        tar.append_data(&mut header, name, content.as_bytes())
+            .unwrap();
+    }
+    for (entry_type, name, target) in [
+        (tar::EntryType::Symlink, "project/latest", "run.sh"),
+        (tar::EntryType::Symlink, "project/lib", "src"),
+        (tar::EntryType::Link, "project/copy.c", "project/src/main.c"),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_size(0);
+        header.set_mode(0o777);
+        tar.append_link(&mut header, name, target).unwrap();
+    }
+    tar.finish().unwrap();
+
+    // C: a zip with a symlink
+    let mut zip = zip::ZipWriter::new(File::create(zips.join("Sources B13264R-C.zip")).unwrap());
+    let options = zip::write::FileOptions::default();
+    zip.start_file("project/src/main.c", options).unwrap();
+    zip.write_all(b"int version = 3;\n").unwrap();
+    zip.add_symlink("project/latest", "src/main.c", options)
+        .unwrap();
+    zip.finish().unwrap();
+
+    // D: a folder with symlinks
+    let folder = zips.join("Sources B13264R-D/project");
+    std::fs::create_dir_all(folder.join("src")).unwrap();
+    std::fs::write(folder.join("src/main.c"), "int version = 4;\n").unwrap();
+    std::fs::write(folder.join("run.sh"), "make\n").unwrap();
+    std::fs::set_permissions(
+        folder.join("run.sh"),
+        std::fs::Permissions::from_mode(0o744),
+    )
+    .unwrap();
+    symlink("run.sh", folder.join("latest")).unwrap();
+    symlink("src", folder.join("lib")).unwrap();
+
+    let bases = ["B13264R-A"];
+    migration.push(&bases).unwrap();
+    for (repo, backend) in [("odb", Backend::Odb), ("piped", Backend::FastImport)] {
+        migration
+            .push_with(PushConfig {
+                repo_path: Some(migration.path(repo)),
+                backend: Some(backend),
+                ..migration.push_config(&bases)
+            })
+            .unwrap();
+    }
+
+    let worktree = migration.repo();
+    let odb = git2::Repository::open(migration.path("odb")).unwrap();
+    let piped = git2::Repository::open(migration.path("piped")).unwrap();
+    for reference in ["B13264R-B", "B13264R-C", "B13264R-D"] {
+        let commit = tagged_commit(&worktree, reference);
+        assert_eq!(
+            tagged_commit(&odb, reference).tree_id(),
+            commit.tree_id(),
+            "{}",
+            reference
+        );
+        assert_eq!(
+            tagged_commit(&piped, reference).tree_id(),
+            commit.tree_id(),
+            "{}",
+            reference
+        );
+    }
+    let entry = |reference: &str, path: &str| {
+        let tree = tagged_commit(&worktree, reference).tree().unwrap();
+        let entry = tree.get_path(path.as_ref()).unwrap();
+        (entry.filemode(), entry.id())
+    };
+    let blob = |content: &str| git2::Oid::hash_object(git2::ObjectType::Blob, content.as_bytes());
+    for reference in ["B13264R-B", "B13264R-D"] {
+        assert_eq!(
+            entry(reference, "latest"),
+            (0o120000, blob("run.sh").unwrap())
+        );
+        assert_eq!(entry(reference, "lib"), (0o120000, blob("src").unwrap()));
+        assert_eq!(entry(reference, "run.sh").0, 0o100755);
+    }
+    assert_eq!(
+        entry("B13264R-B", "copy.c"),
+        entry("B13264R-B", "src/main.c")
+    );
+    assert_eq!(
+        entry("B13264R-C", "latest"),
+        (0o120000, blob("src/main.c").unwrap())
+    );
+}
+
+#[test]
+fn verify_reports_the_missing_tags() {
+    let migration = sd_ct();
+    migration.prepare().unwrap();
+    migration.push(&["B13264R-A"]).unwrap();
+    let overrides = migration.push_config(&["B13264R-A"]);
+    let settings = TableSettings::resolve(&overrides, &PushConfig::default()).unwrap();
+    verify(&settings).unwrap();
+
+    migration.repo().tag_delete("B13264R-C").unwrap();
+    let error = verify(&settings).unwrap_err().to_string();
+    assert!(
+        error.starts_with("1 of 7 references are not tagged"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn a_cycle_aborts_before_the_previous_tables_are_deleted() {
+    let mut migration = Migration::new();
+    migration.workbook(
+        "code.xlsx",
+        LogicielType::Code,
+        &[row(("L", "1"), "", 44000.0, "Jean Dupont", "root")],
+    );
+    migration.prepare().unwrap();
+
+    let mut looping = Migration {
+        dir: migration.dir,
+        workbooks: Vec::new(),
+    };
+    looping.workbook(
+        "loop.xlsx",
+        LogicielType::Code,
+        &[
+            row(("L", "1"), "L-2", 44000.0, "Jean Dupont", "loop 1"),
+            row(("L", "2"), "L-1", 44001.0, "Jean Dupont", "loop 2"),
+        ],
+    );
+    let error = looping.prepare().unwrap_err().to_string();
+    assert!(
+        error.starts_with("1 cycle(s) in the Based On column"),
+        "{}",
+        error
+    );
+    assert!(error.contains("loop.xlsx row 7"), "{}", error);
+    let df = read_lineage_table(&looping.path("parquets")).unwrap();
+    assert_eq!(df.height(), 1);
+}
+
+#[test]
+fn a_broken_cycle_starts_at_its_oldest_version() {
+    let mut migration = Migration::new();
+    migration.workbook(
+        "loop.xlsx",
+        LogicielType::Code,
+        &[
+            // The most recent version of the loop comes first in the workbook and in the table
+            row(("L", "2"), "L-1", 44001.0, "Jean Dupont", "loop 2"),
+            row(("L", "1"), "L-2", 44000.0, "Jean Dupont", "loop 1"),
+            row(("S", "1"), "S-1", 44002.0, "Jean Dupont", "based on itself"),
+            row(("S", "2"), "S-1", 44003.0, "Jean Dupont", "after itself"),
+        ],
+    );
+    let settings = PrepareSettings::resolve(
+        PrepareConfig {
+            on_cycle: Some(CyclePolicy::Break),
+            ..migration.prepare_config()
+        },
+        PrepareConfig::default(),
+    )
+    .unwrap();
+    git_push_automatic::prepare::prepare(&settings).unwrap();
+
+    let df = read_lineage_table(&migration.path("parquets")).unwrap();
+    let references = df.column(REFERENCE).unwrap().str().unwrap();
+    let parents = df.column(PARENT_REFERENCE).unwrap().str().unwrap();
+    let roots = df.column(LINEAGE_ROOT).unwrap().str().unwrap();
+    let depths = df.column(DEPTH).unwrap().u32().unwrap();
+    let version = |reference: &str| {
+        let row = references
+            .into_iter()
+            .position(|r| r == Some(reference))
+            .unwrap();
+        (parents.get(row), roots.get(row), depths.get(row))
+    };
+    // The oldest version of the loop loses its Based On and becomes the root
+    assert_eq!(version("L-1"), (None, Some("L-1"), Some(0)));
+    assert_eq!(version("L-2"), (Some("L-1"), Some("L-1"), Some(1)));
+    // A version based on itself is a root
+    assert_eq!(version("S-1"), (None, Some("S-1"), Some(0)));
+    assert_eq!(version("S-2"), (Some("S-1"), Some("S-1"), Some(1)));
+}
+
+#[test]
+fn an_incremental_push_appends_the_new_versions() {
+    let mut migration = Migration::new();
+    migration.workbook(
+        "code.xlsx",
+        LogicielType::Code,
+        &[
+            row(("B13264R", "A"), "", 44000.0, "Jean Dupont", "initial"),
+            row(("B13264R", "B"), "B13264R-A", 44010.0, "Jean Dupont", "fix"),
+            row(
+                ("B13264R", "C"),
+                "B13264R-B",
+                44020.0,
+                "Zoé Martin",
+                "feature",
+            ),
+        ],
+    );
+    for (reference, version) in [("B13264R-A", "1"), ("B13264R-B", "2"), ("B13264R-G", "7")] {
+        migration.zip(
+            reference,
+            &[
+                ("src/main.c", &format!("int version = {};\n", version)),
+                ("README", reference),
+            ],
+        );
+    }
+    migration.prepare().unwrap();
+    migration.push(&["B13264R-A"]).unwrap();
+    let published: Vec<(String, git2::Oid)> = {
+        let repo = migration.repo();
+        ["B13264R-A", "B13264R-B", "B13264R-C"]
+            .into_iter()
+            .map(|reference| (reference.to_owned(), tagged_commit(&repo, reference).id()))
+            .collect()
+    };
+
+    // The next export adds a version after C and a second version based on B
+    migration.workbook(
+        "ct_code.xlsx",
+        LogicielType::Code,
+        &[
+            row(
+                ("B13264R", "D"),
+                "B13264R-C",
+                44030.0,
+                "Jean Dupont",
+                "after C",
+            ),
+            row(
+                ("B13264R", "G"),
+                "B13264R-B",
+                44040.0,
+                "Zoé Martin",
+                "from B",
+            ),
+        ],
+    );
+    migration.prepare().unwrap();
+    let mut overrides = migration.push_config(&["B13264R-A"]);
+    overrides.incremental = Some(true);
+    migration.push_with(overrides).unwrap();
+    let repo = migration.repo();
+
+    for (reference, commit_id) in &published {
+        assert_eq!(
+            tagged_commit(&repo, reference).id(),
+            *commit_id,
+            "{}",
+            reference
+        );
+    }
+    let d = tagged_commit(&repo, "B13264R-D");
+    assert_eq!(
+        d.parent_id(0).unwrap(),
+        tagged_commit(&repo, "B13264R-C").id()
+    );
+    assert_eq!(branch_head(&repo, "B13264R-A").id(), d.id());
+    // C has no zip, D reuses the sources of the last zip found before it
+    assert_eq!(file_content(&repo, &d, "src/main.c"), "int version = 2;\n");
+
+    let g = tagged_commit(&repo, "B13264R-G");
+    assert_eq!(
+        g.parent_id(0).unwrap(),
+        tagged_commit(&repo, "B13264R-B").id()
+    );
+    assert_eq!(branch_head(&repo, "B13264R-G").id(), g.id());
+    assert_eq!(file_content(&repo, &g, "src/main.c"), "int version = 7;\n");
+
+    // Nothing left to import: a second incremental push changes nothing
+    let heads: Vec<git2::Oid> = ["B13264R-A", "B13264R-G"]
+        .iter()
+        .map(|branch| branch_head(&repo, branch).id())
+        .collect();
+    let mut overrides = migration.push_config(&["B13264R-A"]);
+    overrides.incremental = Some(true);
+    migration.push_with(overrides).unwrap();
+    let repo = migration.repo();
+    for (branch, head) in ["B13264R-A", "B13264R-G"].iter().zip(heads) {
+        assert_eq!(branch_head(&repo, branch).id(), head, "{}", branch);
+    }
+
+    // A folder that isn't a repository stops the push, it is never deleted
+    let folder = migration.path("not-a-repo");
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(folder.join("notes.txt"), "keep me").unwrap();
+    let error = migration
+        .push_with(PushConfig {
+            repo_path: Some(folder.clone()),
+            incremental: Some(true),
+            ..migration.push_config(&["B13264R-A"])
+        })
+        .expect_err("no error")
+        .to_string();
+    assert!(error.contains("is not a git repository"), "{}", error);
+    assert_eq!(
+        std::fs::read_to_string(folder.join("notes.txt")).unwrap(),
+        "keep me"
+    );
+}